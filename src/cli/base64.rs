@@ -1,6 +1,10 @@
 use core::fmt;
-use std::str::FromStr;
+use std::{
+    io::{self, Write},
+    str::FromStr,
+};
 
+use anyhow::bail;
 use clap::Parser;
 use enum_dispatch::enum_dispatch;
 
@@ -13,6 +17,13 @@ pub enum Base64SubCommand {
     Encode(Base64EncodeOpts),
     #[command(name = "decode", about = "Decode a base64 string")]
     Decode(Base64DecodeOpts),
+    #[command(
+        name = "doc",
+        about = "Encode/decode values inside a json/yaml document"
+    )]
+    Doc(Base64DocOpts),
+    #[command(name = "datauri", about = "Build a data URI from a file, or parse one")]
+    DataUri(Base64DataUriOpts),
 }
 
 #[derive(Debug, Parser)]
@@ -22,23 +33,86 @@ pub struct Base64EncodeOpts {
 
     #[arg(long, value_parser = parse_base64_format,  default_value = "standard")]
     pub format: Base64Format,
+    /// break lines after this many characters, e.g. 76 for MIME
+    #[arg(long)]
+    pub wrap: Option<usize>,
 }
 
 #[derive(Debug, Parser)]
 pub struct Base64DecodeOpts {
     #[arg(short, long, value_parser = verify_file, default_value = "-")]
     pub input: String,
-    #[arg(short, long, default_value = "standard")]
+    #[arg(short, long, value_parser = parse_base64_format, default_value = "standard")]
     pub format: Base64Format,
+    /// where the raw decoded bytes go, `-` is stdout
+    #[arg(short, long, default_value = "-")]
+    pub output: String,
+    /// print the decoded data as utf-8 text instead of raw bytes
+    #[arg(long, default_value_t = false, conflicts_with = "output")]
+    pub text: bool,
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Parser)]
+pub struct Base64DocOpts {
+    #[arg(short, long, value_parser = verify_file, default_value = "-")]
+    pub input: String,
+    /// dot separated path of the values to convert, `*` matches any key, e.g. `data.*`
+    #[arg(short, long, required = true)]
+    pub path: Vec<String>,
+    /// decode the values instead of encoding them
+    #[arg(short, long, default_value_t = false)]
+    pub decode: bool,
+    #[arg(short, long, value_parser = parse_base64_format, default_value = "standard")]
+    pub format: Base64Format,
+    /// json or yaml, guessed from the input file extension (yaml for stdin)
+    #[arg(long, value_parser = parse_doc_format)]
+    pub doc_format: Option<DocFormat>,
+}
+
+#[derive(Debug, Parser)]
+pub struct Base64DataUriOpts {
+    #[arg(short, long, value_parser = verify_file, default_value = "-")]
+    pub input: String,
+    /// MIME type of the data, guessed from the file extension or content if omitted
+    #[arg(long, conflicts_with = "parse")]
+    pub mime: Option<String>,
+    /// parse a data URI instead, its MIME type is printed on stderr
+    #[arg(short, long, default_value_t = false)]
+    pub parse: bool,
+    /// where the data URI or the parsed bytes go, `-` is stdout
+    #[arg(short, long, default_value = "-")]
+    pub output: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Base64Format {
+    /// `+/` alphabet, padded
     Standard,
+    StandardNoPad,
+    /// `-_` alphabet, unpadded
     UrlSafe,
+    UrlSafePad,
+    /// `./A-Za-z0-9` alphabet used by bcrypt, unpadded
+    Bcrypt,
+    /// `./0-9A-Za-z` alphabet used by crypt(3), unpadded
+    Crypt,
+    /// decode only: accepts both the standard and the url-safe alphabet, padded or not
+    Auto,
+}
+
+/// Documents `rcli base64 doc` can edit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DocFormat {
+    Json,
+    /// may hold several `---` separated documents
+    Yaml,
 }
 
-fn parse_base64_format(format: &str) -> Result<Base64Format, anyhow::Error> {
+pub fn parse_base64_format(format: &str) -> Result<Base64Format, anyhow::Error> {
+    format.parse()
+}
+
+fn parse_doc_format(format: &str) -> Result<DocFormat, anyhow::Error> {
     format.parse()
 }
 
@@ -46,9 +120,15 @@ impl FromStr for Base64Format {
     type Err = anyhow::Error;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        match s {
+        // accept `url_safe`, `url-safe` and `urlsafe` alike
+        match s.replace(['_', '-'], "").as_str() {
             "standard" => Ok(Base64Format::Standard),
-            "url_safe" => Ok(Base64Format::UrlSafe),
+            "standardnopad" => Ok(Base64Format::StandardNoPad),
+            "urlsafe" => Ok(Base64Format::UrlSafe),
+            "urlsafepad" => Ok(Base64Format::UrlSafePad),
+            "bcrypt" => Ok(Base64Format::Bcrypt),
+            "crypt" => Ok(Base64Format::Crypt),
+            "auto" => Ok(Base64Format::Auto),
             _ => Err(anyhow::anyhow!("Invalid format")),
         }
     }
@@ -58,7 +138,12 @@ impl From<Base64Format> for &'static str {
     fn from(format: Base64Format) -> Self {
         match format {
             Base64Format::Standard => "standard",
+            Base64Format::StandardNoPad => "standard-nopad",
             Base64Format::UrlSafe => "urlsafe",
+            Base64Format::UrlSafePad => "urlsafe-pad",
+            Base64Format::Bcrypt => "bcrypt",
+            Base64Format::Crypt => "crypt",
+            Base64Format::Auto => "auto",
         }
     }
 }
@@ -69,11 +154,91 @@ impl fmt::Display for Base64Format {
     }
 }
 
+impl FromStr for DocFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "json" => Ok(DocFormat::Json),
+            "yaml" | "yml" => Ok(DocFormat::Yaml),
+            _ => Err(anyhow::anyhow!(
+                "Invalid document format, expected json or yaml"
+            )),
+        }
+    }
+}
+
+impl From<DocFormat> for &'static str {
+    fn from(format: DocFormat) -> Self {
+        match format {
+            DocFormat::Json => "json",
+            DocFormat::Yaml => "yaml",
+        }
+    }
+}
+
+impl fmt::Display for DocFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", Into::<&str>::into(*self))
+    }
+}
+
 impl CmdExector for Base64EncodeOpts {
     async fn execute(self) -> anyhow::Result<()> {
         let mut reader = crate::get_reader(&self.input)?;
-        let ret = crate::process_encode(&mut reader, self.format)?;
-        println!("{}", ret);
+        let mut writer = io::stdout().lock();
+        crate::process_encode(&mut reader, &mut writer, self.format, self.wrap)?;
+        writeln!(writer)?;
+        Ok(())
+    }
+}
+
+impl CmdExector for Base64DocOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        let doc_format = self.doc_format.unwrap_or_else(|| {
+            if self.input.ends_with(".json") {
+                DocFormat::Json
+            } else {
+                DocFormat::Yaml
+            }
+        });
+        let mut reader = crate::get_reader(&self.input)?;
+        let mut writer = io::stdout().lock();
+        crate::process_b64_doc(
+            &mut reader,
+            &mut writer,
+            &self.path,
+            self.decode,
+            self.format,
+            doc_format,
+        )
+    }
+}
+
+impl CmdExector for Base64DataUriOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        if crate::is_same_file(&self.input, &self.output) {
+            bail!(
+                "{} is the input file, refusing to overwrite it",
+                self.output
+            );
+        }
+        let mut reader = crate::get_reader(&self.input)?;
+        let mut writer = crate::get_lazy_writer(&self.output)?;
+        if self.parse {
+            let mime = crate::process_data_uri_decode(&mut reader, &mut writer)?;
+            eprintln!("{}", mime);
+        } else {
+            crate::process_data_uri_encode(
+                &mut reader,
+                &mut writer,
+                &self.input,
+                self.mime.as_deref(),
+            )?;
+            if self.output == "-" {
+                writeln!(writer)?;
+            }
+        }
         Ok(())
     }
 }
@@ -81,8 +246,20 @@ impl CmdExector for Base64EncodeOpts {
 impl CmdExector for Base64DecodeOpts {
     async fn execute(self) -> anyhow::Result<()> {
         let mut reader = crate::get_reader(&self.input)?;
-        let ret = crate::process_decode(&mut reader, self.format)?;
-        println!("{}", ret);
+        if self.text {
+            let mut ret = Vec::new();
+            crate::process_decode(&mut reader, &mut ret, self.format)?;
+            println!("{}", String::from_utf8(ret)?);
+        } else {
+            if crate::is_same_file(&self.input, &self.output) {
+                bail!(
+                    "{} is the input file, refusing to overwrite it",
+                    self.output
+                );
+            }
+            let mut writer = crate::get_lazy_writer(&self.output)?;
+            crate::process_decode(&mut reader, &mut writer, self.format)?;
+        }
         Ok(())
     }
 }
@@ -0,0 +1,123 @@
+use core::fmt;
+use std::{
+    io::{self, Write},
+    str::FromStr,
+};
+
+use anyhow::bail;
+use clap::Parser;
+
+use crate::{
+    get_lazy_writer, get_reader, is_same_file, parse_base64_format, process_codec_decode,
+    process_codec_encode, verify_file, Base64Format, CmdExector,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CodecFormat {
+    Base64,
+    Hex,
+    Base32,
+    Base58,
+    Url,
+}
+
+#[derive(Debug, Parser)]
+pub struct EncodeOpts {
+    #[arg(short, long, value_parser = verify_file, default_value = "-")]
+    pub input: String,
+    /// base64, hex or base32 stream the input, base58 and url read it all into memory
+    #[arg(short, long, value_parser = parse_codec_format, default_value = "base64")]
+    pub codec: CodecFormat,
+    /// base64 variant, only used with `--codec base64`
+    #[arg(long, value_parser = parse_base64_format, default_value = "standard")]
+    pub format: Base64Format,
+    /// break base64 lines after this many characters
+    #[arg(long)]
+    pub wrap: Option<usize>,
+}
+
+#[derive(Debug, Parser)]
+pub struct DecodeOpts {
+    #[arg(short, long, value_parser = verify_file, default_value = "-")]
+    pub input: String,
+    /// base64, hex or base32 stream the input, base58 and url read it all into memory
+    #[arg(short, long, value_parser = parse_codec_format, default_value = "base64")]
+    pub codec: CodecFormat,
+    /// base64 variant, only used with `--codec base64`
+    #[arg(long, value_parser = parse_base64_format, default_value = "auto")]
+    pub format: Base64Format,
+    /// where the raw decoded bytes go, `-` is stdout
+    #[arg(short, long, default_value = "-")]
+    pub output: String,
+    /// print the decoded data as utf-8 text instead of raw bytes
+    #[arg(long, default_value_t = false, conflicts_with = "output")]
+    pub text: bool,
+}
+
+fn parse_codec_format(format: &str) -> Result<CodecFormat, anyhow::Error> {
+    format.parse()
+}
+
+impl FromStr for CodecFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "base64" => Ok(CodecFormat::Base64),
+            "hex" => Ok(CodecFormat::Hex),
+            "base32" => Ok(CodecFormat::Base32),
+            "base58" => Ok(CodecFormat::Base58),
+            "url" => Ok(CodecFormat::Url),
+            _ => Err(anyhow::anyhow!("Invalid codec")),
+        }
+    }
+}
+
+impl From<CodecFormat> for &'static str {
+    fn from(format: CodecFormat) -> Self {
+        match format {
+            CodecFormat::Base64 => "base64",
+            CodecFormat::Hex => "hex",
+            CodecFormat::Base32 => "base32",
+            CodecFormat::Base58 => "base58",
+            CodecFormat::Url => "url",
+        }
+    }
+}
+
+impl fmt::Display for CodecFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", Into::<&str>::into(*self))
+    }
+}
+
+impl CmdExector for EncodeOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        let mut reader = get_reader(&self.input)?;
+        let mut writer = io::stdout().lock();
+        process_codec_encode(&mut reader, &mut writer, self.codec, self.format, self.wrap)?;
+        writeln!(writer)?;
+        Ok(())
+    }
+}
+
+impl CmdExector for DecodeOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        let mut reader = get_reader(&self.input)?;
+        if self.text {
+            let mut ret = Vec::new();
+            process_codec_decode(&mut reader, &mut ret, self.codec, self.format)?;
+            println!("{}", String::from_utf8(ret)?);
+        } else {
+            if is_same_file(&self.input, &self.output) {
+                bail!(
+                    "{} is the input file, refusing to overwrite it",
+                    self.output
+                );
+            }
+            let mut writer = get_lazy_writer(&self.output)?;
+            process_codec_decode(&mut reader, &mut writer, self.codec, self.format)?;
+        }
+        Ok(())
+    }
+}
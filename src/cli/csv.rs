@@ -1,45 +1,297 @@
-use std::{fmt, str::FromStr};
+use std::{fmt, io::Write, str::FromStr};
 
-use clap::Parser;
+use anyhow::bail;
+use clap::{ArgAction, Args, Parser};
+use enum_dispatch::enum_dispatch;
 
-use crate::{verify_file, CmdExector};
+use crate::{
+    format_stats_table, get_lazy_writer, get_reader, is_same_file, process_csv_stats, verify_file,
+    CmdExector, ColumnSelection, CsvConvertOptions, CsvReadOptions, CsvSchema, Filter,
+};
 
 #[derive(Debug, Clone, Copy)]
 pub enum OutputFormat {
     Json,
     Yaml,
+    Toml,
+    Ndjson,
+    Csv,
 }
 
+/// How `rcli csv --lenient` deals with malformed rows.
+#[derive(Debug, Clone, Copy)]
+pub enum LenientMode {
+    /// drop the row
+    Skip,
+    /// fill short rows with empty cells, drop the rest
+    Pad,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum StatsFormat {
+    Table,
+    Json,
+}
+
+/// `rcli csv` converts by default, other commands are subcommands, e.g. `rcli csv stats`.
 #[derive(Debug, Parser)]
+#[command(args_conflicts_with_subcommands = true)]
 pub struct CsvOpts {
-    #[arg(short, long, value_parser = verify_file)]
+    #[command(subcommand)]
+    pub cmd: Option<CsvSubCommand>,
+
+    #[command(flatten)]
+    pub convert: CsvConvertOpts,
+}
+
+#[derive(Debug, Parser)]
+#[enum_dispatch(CmdExector)]
+pub enum CsvSubCommand {
+    #[command(
+        name = "stats",
+        about = "Show type, nulls and value statistics per column"
+    )]
+    Stats(CsvStatsOpts),
+}
+
+/// Options shared by everything that reads csv.
+#[derive(Debug, Args)]
+pub struct CsvReadArgs {
+    #[arg(short, long, value_parser = verify_file, default_value = "-")]
     pub input: String,
 
+    #[arg(short, long, value_parser = parse_delimiter, default_value = ",")]
+    pub delimiter: char,
+
+    #[arg(long, default_value_t = true, action = ArgAction::Set)]
+    pub header: bool,
+
+    /// column names, required for headerless input unless `col0`, `col1`, ... is fine
+    #[arg(long, value_delimiter = ',')]
+    pub columns: Option<Vec<String>>,
+}
+
+#[derive(Debug, Args)]
+pub struct CsvConvertOpts {
+    #[command(flatten)]
+    pub read: CsvReadArgs,
+
+    /// `-` writes to stdout, defaults to `output.<format>`
     #[arg(short, long)] // "output.josn".into()
     pub output: Option<String>,
 
     #[arg(long, value_parser = parse_format, default_value = "json")]
     pub format: OutputFormat,
 
-    #[arg(short, long, default_value_t = ',')]
-    pub delimiter: char,
+    /// convert the other way: read json/yaml/toml/ndjson and write csv
+    #[arg(long, value_parser = parse_format, conflicts_with_all = ["infer", "schema", "select", "exclude", "rename", "filter", "nested"])]
+    pub from: Option<OutputFormat>,
 
-    #[arg(long, default_value_t = true)]
-    pub header: bool,
+    /// delimiter used when the output format is csv, defaults to `--delimiter`
+    #[arg(long, value_parser = parse_delimiter)]
+    pub output_delimiter: Option<char>,
+
+    /// convert numbers, booleans and empty cells to native values
+    #[arg(long, default_value_t = false)]
+    pub infer: bool,
+
+    /// yaml/json/toml file pinning column types, e.g. `Kit Number: integer`
+    #[arg(long, value_parser = verify_file)]
+    pub schema: Option<String>,
+
+    /// only output these columns, in this order
+    #[arg(long, value_delimiter = ',', conflicts_with = "exclude")]
+    pub select: Option<Vec<String>>,
+
+    /// drop these columns
+    #[arg(long, value_delimiter = ',')]
+    pub exclude: Vec<String>,
+
+    /// rename a column, e.g. `--rename "Kit Number=kit_number"`
+    #[arg(long, value_parser = parse_rename)]
+    pub rename: Vec<(String, String)>,
+
+    /// only output matching rows, e.g. `--where 'Nationality == "Italy"'`
+    #[arg(long = "where", value_parser = parse_filter)]
+    pub filter: Option<Filter>,
+
+    /// build nested objects/arrays from headers like `address.city` and `tags[0]`
+    #[arg(long, default_value_t = false)]
+    pub nested: bool,
+
+    /// skip (or pad) malformed rows instead of failing, a summary goes to stderr
+    #[arg(long, value_parser = parse_lenient_mode)]
+    pub lenient: Option<LenientMode>,
+
+    /// write the dropped rows to this csv file, with their line and the reason
+    #[arg(long, requires = "lenient")]
+    pub rejects: Option<String>,
+}
+
+#[derive(Debug, Parser)]
+pub struct CsvStatsOpts {
+    #[command(flatten)]
+    pub read: CsvReadArgs,
+
+    #[arg(long, value_parser = parse_stats_format, default_value = "table")]
+    pub format: StatsFormat,
+
+    /// how many of the most common values to show per column
+    #[arg(long, default_value_t = 3)]
+    pub top: usize,
+}
+
+impl From<CsvReadArgs> for CsvReadOptions {
+    fn from(args: CsvReadArgs) -> Self {
+        Self {
+            delimiter: args.delimiter as u8,
+            has_headers: args.header,
+            columns: args.columns,
+        }
+    }
 }
 
 impl CmdExector for CsvOpts {
     async fn execute(self) -> anyhow::Result<()> {
+        match self.cmd {
+            Some(cmd) => cmd.execute().await,
+            None => self.convert.execute().await,
+        }
+    }
+}
+
+impl CmdExector for CsvStatsOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        let mut reader = get_reader(&self.read.input)?;
+        let stats = process_csv_stats(&mut reader, &self.read.into(), self.top)?;
+        match self.format {
+            StatsFormat::Table => print!("{}", format_stats_table(&stats)),
+            StatsFormat::Json => println!("{}", serde_json::to_string_pretty(&stats)?),
+        }
+        Ok(())
+    }
+}
+
+impl CmdExector for CsvConvertOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        let format = if self.from.is_some() {
+            OutputFormat::Csv
+        } else {
+            self.format
+        };
         let output = if let Some(output) = self.output {
             output
         } else {
-            format!("output.{}", self.format)
+            format!("output.{}", format)
+        };
+        // check everything before the output is touched, it may be precious
+        for file in [Some(&output), self.rejects.as_ref()].into_iter().flatten() {
+            if is_same_file(&self.read.input, file) {
+                bail!("{} is the input file, refusing to overwrite it", file);
+            }
+        }
+        let schema = match self.schema {
+            Some(path) => Some(CsvSchema::load(path)?),
+            None => None,
+        };
+        let mut reader = get_reader(&self.read.input)?;
+        // only created once the headers are read and the options checked against them
+        let mut writer = get_lazy_writer(&output)?;
+        let output_delimiter = self.output_delimiter.map(|c| c as u8);
+        if let Some(from) = self.from {
+            let delimiter = output_delimiter.unwrap_or(self.read.delimiter as u8);
+            return crate::process_csv_from(&mut reader, &mut writer, from, delimiter);
+        }
+        let opts = CsvConvertOptions {
+            read: self.read.into(),
+            infer: self.infer,
+            schema,
+            output_delimiter,
+            selection: ColumnSelection {
+                select: self.select,
+                exclude: self.exclude,
+                rename: self.rename,
+            },
+            filter: self.filter,
+            nested: self.nested,
+            lenient: self.lenient,
         };
-        crate::process_csv(&self.input, output, self.format)
+        let mut rejects = match &self.rejects {
+            Some(rejects) => Some(get_lazy_writer(rejects)?),
+            None => None,
+        };
+        let report = crate::process_csv(
+            &mut reader,
+            &mut writer,
+            rejects.as_mut().map(|w| w as &mut dyn Write),
+            format,
+            &opts,
+        )?;
+
+        if report.padded > 0 || report.rejected > 0 {
+            eprintln!(
+                "Wrote {} rows, padded {} short rows, skipped {} malformed rows",
+                report.written, report.padded, report.rejected
+            );
+        }
+        if !report.rejected_lines.is_empty() {
+            let lines = report
+                .rejected_lines
+                .iter()
+                .map(u64::to_string)
+                .collect::<Vec<_>>();
+            let more = if report.rejected > lines.len() {
+                ", ..."
+            } else {
+                ""
+            };
+            eprintln!("Skipped lines: {}{}", lines.join(", "), more);
+        }
+        Ok(())
+    }
+}
+
+fn parse_lenient_mode(mode: &str) -> Result<LenientMode, anyhow::Error> {
+    mode.parse()
+}
+
+fn parse_stats_format(format: &str) -> Result<StatsFormat, anyhow::Error> {
+    format.parse()
+}
+
+fn parse_rename(rename: &str) -> Result<(String, String), &'static str> {
+    match rename.split_once('=') {
+        Some((old, new)) if !old.is_empty() && !new.is_empty() => {
+            Ok((old.to_string(), new.to_string()))
+        }
+        _ => Err("The rename must look like old=new"),
+    }
+}
+
+fn parse_filter(filter: &str) -> Result<Filter, anyhow::Error> {
+    filter.parse()
+}
+
+fn parse_delimiter(delimiter: &str) -> Result<char, &'static str> {
+    // allow `\t` and `tab` since a literal tab is awkward to type in a shell
+    let c = match delimiter {
+        "\\t" | "tab" => '\t',
+        _ => {
+            let mut chars = delimiter.chars();
+            match (chars.next(), chars.next()) {
+                (Some(c), None) => c,
+                _ => return Err("The delimiter must be a single character"),
+            }
+        }
+    };
+    if c.is_ascii() {
+        Ok(c)
+    } else {
+        Err("The delimiter must be an ascii character")
     }
 }
 
-fn parse_format(format: &str) -> Result<OutputFormat, anyhow::Error> {
+pub fn parse_format(format: &str) -> Result<OutputFormat, anyhow::Error> {
     format.parse()
 }
 
@@ -48,6 +300,9 @@ impl From<OutputFormat> for &'static str {
         match format {
             OutputFormat::Json => "json",
             OutputFormat::Yaml => "yaml",
+            OutputFormat::Toml => "toml",
+            OutputFormat::Ndjson => "ndjson",
+            OutputFormat::Csv => "csv",
         }
     }
 }
@@ -59,6 +314,9 @@ impl FromStr for OutputFormat {
         match s {
             "json" => Ok(OutputFormat::Json),
             "yaml" => Ok(OutputFormat::Yaml),
+            "toml" => Ok(OutputFormat::Toml),
+            "ndjson" => Ok(OutputFormat::Ndjson),
+            "csv" => Ok(OutputFormat::Csv),
             _ => Err(anyhow::anyhow!("Invalid format")),
         }
     }
@@ -69,3 +327,57 @@ impl fmt::Display for OutputFormat {
         write!(f, "{}", Into::<&str>::into(*self))
     }
 }
+
+impl From<StatsFormat> for &'static str {
+    fn from(format: StatsFormat) -> Self {
+        match format {
+            StatsFormat::Table => "table",
+            StatsFormat::Json => "json",
+        }
+    }
+}
+
+impl FromStr for StatsFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "table" => Ok(StatsFormat::Table),
+            "json" => Ok(StatsFormat::Json),
+            _ => Err(anyhow::anyhow!("Invalid format")),
+        }
+    }
+}
+
+impl fmt::Display for StatsFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", Into::<&str>::into(*self))
+    }
+}
+
+impl From<LenientMode> for &'static str {
+    fn from(mode: LenientMode) -> Self {
+        match mode {
+            LenientMode::Skip => "skip",
+            LenientMode::Pad => "pad",
+        }
+    }
+}
+
+impl FromStr for LenientMode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "skip" => Ok(LenientMode::Skip),
+            "pad" => Ok(LenientMode::Pad),
+            _ => Err(anyhow::anyhow!("Invalid lenient mode")),
+        }
+    }
+}
+
+impl fmt::Display for LenientMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", Into::<&str>::into(*self))
+    }
+}
@@ -1,20 +1,138 @@
-use clap::Parser;
+use std::{
+    fs,
+    io::{self, BufRead, BufReader},
+};
+
+use anyhow::bail;
+use clap::{ArgAction, Args, Parser};
+use csv::StringRecord;
+use enum_dispatch::enum_dispatch;
 use zxcvbn::zxcvbn;
 
-use crate::CmdExector;
+use crate::{
+    parse_format, verify_file, CmdExector, GenPassConfig, OutputFormat, PassphraseConfig,
+    PasswordPolicy, PolicyFile, Template,
+};
 
+/// `rcli genpass` generates by default, other commands are subcommands, e.g. `rcli genpass check`.
 #[derive(Debug, Parser)]
+#[command(args_conflicts_with_subcommands = true)]
 pub struct GenPassOpts {
+    #[command(subcommand)]
+    pub cmd: Option<GenPassSubCommand>,
+
+    #[command(flatten)]
+    pub generate: GenPassGenerateOpts,
+}
+
+#[derive(Debug, Parser)]
+#[enum_dispatch(CmdExector)]
+pub enum GenPassSubCommand {
+    #[command(
+        name = "check",
+        about = "Check the strength of passwords, one per line"
+    )]
+    Check(GenPassCheckOpts),
+}
+
+#[derive(Debug, Args)]
+pub struct GenPassGenerateOpts {
     #[arg(short, long, default_value_t = 16, value_parser = least_length)]
-    pub length: u8,
-    #[arg(long, default_value_t = false)]
+    pub length: usize,
+    #[arg(long, default_value_t = false, num_args = 0..=1, default_missing_value = "true", action = ArgAction::Set)]
     pub uppercase: bool,
-    #[arg(long, default_value_t = true)]
+    #[arg(long, default_value_t = true, num_args = 0..=1, default_missing_value = "true", action = ArgAction::Set)]
     pub lowercase: bool,
-    #[arg(long, default_value_t = true)]
+    #[arg(long, default_value_t = true, num_args = 0..=1, default_missing_value = "true", action = ArgAction::Set)]
     pub number: bool,
-    #[arg(short, long, default_value_t = true)]
+    #[arg(short, long, default_value_t = true, num_args = 0..=1, default_missing_value = "true", action = ArgAction::Set)]
     pub symbol: bool,
+    /// custom symbol set used instead of `!@#$%^&*_`
+    #[arg(long, allow_hyphen_values = true)]
+    pub symbols: Option<String>,
+    /// characters that must not appear in the password
+    #[arg(long, default_value = "", allow_hyphen_values = true)]
+    pub exclude: String,
+    /// also leave out look-alikes such as `1`, `O` and `0`, `I` and `l` are never used
+    #[arg(long, default_value_t = false)]
+    pub no_ambiguous: bool,
+    #[arg(long, default_value_t = 1)]
+    pub min_upper: usize,
+    #[arg(long, default_value_t = 1)]
+    pub min_lower: usize,
+    #[arg(long, default_value_t = 1)]
+    pub min_number: usize,
+    #[arg(long, default_value_t = 1)]
+    pub min_symbol: usize,
+    /// generate a passphrase of random words instead
+    #[arg(long, default_value_t = false, conflicts_with_all = ["policy", "template"])]
+    pub passphrase: bool,
+    #[arg(long, default_value_t = 6, requires = "passphrase")]
+    pub words: usize,
+    #[arg(
+        long,
+        default_value = "-",
+        requires = "passphrase",
+        allow_hyphen_values = true
+    )]
+    pub separator: String,
+    /// file with one word per line, the bundled list is used by default
+    #[arg(long, value_parser = verify_file, requires = "passphrase")]
+    pub wordlist: Option<String>,
+    /// capitalize the first letter of every word
+    #[arg(long, default_value_t = false, requires = "passphrase")]
+    pub capitalize: bool,
+    /// append a random digit
+    #[arg(long, default_value_t = false, requires = "passphrase")]
+    pub digit: bool,
+    /// number of passwords to generate
+    #[arg(short, long, default_value_t = 1, value_parser = least_count)]
+    pub count: usize,
+    /// write rows with the password, zxcvbn score, guesses and crack time, e.g. json or csv
+    #[arg(long, value_parser = parse_format)]
+    pub format: Option<OutputFormat>,
+    /// generate for a named policy, which replaces the length and character options
+    #[command(flatten)]
+    pub policy: PolicyArgs,
+    /// generate tokens from a template, e.g. `{upper:4}-{upper:4}`, `{syllable:5}{number:2}`
+    /// or `rk_live_{base62:32}{crc}`
+    #[arg(long, value_parser = parse_template, conflicts_with = "policy")]
+    pub template: Option<Template>,
+}
+
+/// A named password policy in a toml or yaml file.
+#[derive(Debug, Args)]
+pub struct PolicyArgs {
+    #[arg(long, value_parser = verify_file, requires = "policy")]
+    pub policy_file: Option<String>,
+    #[arg(long, requires = "policy_file")]
+    pub policy: Option<String>,
+}
+
+impl PolicyArgs {
+    pub fn load(&self) -> anyhow::Result<Option<PasswordPolicy>> {
+        match (&self.policy_file, &self.policy) {
+            (Some(path), Some(name)) => Ok(Some(PolicyFile::load(path)?.get(name)?.clone())),
+            _ => Ok(None),
+        }
+    }
+}
+
+#[derive(Debug, Parser)]
+pub struct GenPassCheckOpts {
+    #[arg(short, long, value_parser = verify_file, default_value = "-")]
+    pub input: String,
+    /// words such as the user or company name that passwords shouldn't be built from
+    #[arg(short, long = "user-input", value_delimiter = ',')]
+    pub user_inputs: Vec<String>,
+    /// exit with an error when any password scores lower than this
+    #[arg(long, value_parser = clap::value_parser!(u8).range(0..=4))]
+    pub min_score: Option<u8>,
+    #[command(flatten)]
+    pub policy: PolicyArgs,
+    /// write rows with the score, guesses, crack time and feedback, e.g. json or csv
+    #[arg(long, value_parser = parse_format)]
+    pub format: Option<OutputFormat>,
 }
 
 fn least_length(length: &str) -> Result<usize, &'static str> {
@@ -28,20 +146,192 @@ fn least_length(length: &str) -> Result<usize, &'static str> {
     }
 }
 
+fn parse_template(template: &str) -> anyhow::Result<Template> {
+    template.parse()
+}
+
+fn least_count(count: &str) -> Result<usize, &'static str> {
+    match count.parse::<usize>() {
+        Ok(0) => Err("At least one password must be generated!"),
+        Ok(count) => Ok(count),
+        Err(_) => Err("Please input a number!"),
+    }
+}
+
+impl From<&GenPassGenerateOpts> for GenPassConfig {
+    fn from(opts: &GenPassGenerateOpts) -> Self {
+        Self {
+            length: opts.length,
+            upper: opts.uppercase,
+            lower: opts.lowercase,
+            number: opts.number,
+            symbol: opts.symbol,
+            symbols: opts.symbols.clone(),
+            exclude: opts.exclude.clone(),
+            no_ambiguous: opts.no_ambiguous,
+            min_upper: opts.min_upper,
+            min_lower: opts.min_lower,
+            min_number: opts.min_number,
+            min_symbol: opts.min_symbol,
+        }
+    }
+}
+
+impl From<&GenPassGenerateOpts> for PassphraseConfig {
+    fn from(opts: &GenPassGenerateOpts) -> Self {
+        Self {
+            words: opts.words,
+            separator: opts.separator.clone(),
+            capitalize: opts.capitalize,
+            digit: opts.digit,
+        }
+    }
+}
+
 impl CmdExector for GenPassOpts {
     async fn execute(self) -> anyhow::Result<()> {
-        let ret = crate::process_genpass(
-            self.length,
-            self.uppercase,
-            self.lowercase,
-            self.number,
-            self.symbol,
-        )?;
-        println!("{}", ret);
-
-        // output password strength in stderr
-        let estimate = zxcvbn(&ret, &[])?;
-        eprintln!("Password strength: {}", estimate.score());
+        match self.cmd {
+            Some(cmd) => cmd.execute().await,
+            None => self.generate.execute().await,
+        }
+    }
+}
+
+impl CmdExector for GenPassCheckOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        let reader = BufReader::new(crate::get_reader(&self.input)?);
+        let user_inputs: Vec<&str> = self.user_inputs.iter().map(|s| s.as_str()).collect();
+        let policy = self.policy.load()?;
+        let headers = StringRecord::from(crate::CHECK_HEADERS.to_vec());
+        let mut writer = match self.format {
+            Some(format) => Some(crate::record_writer(
+                io::stdout().lock(),
+                format,
+                &headers,
+                b',',
+            )?),
+            None => None,
+        };
+
+        let (mut total, mut weak) = (0, 0);
+        for line in reader.lines() {
+            let line = line?;
+            let password = line.trim_end_matches('\r');
+            if password.is_empty() {
+                continue;
+            }
+            let mut check = crate::password_check(password, &user_inputs)?;
+            if let Some(policy) = &policy {
+                check.violations = policy.violations(password);
+            }
+            total += 1;
+            if self.min_score.is_some_and(|min| check.report.score < min)
+                || !check.violations.is_empty()
+            {
+                weak += 1;
+            }
+
+            match writer.as_mut() {
+                Some(writer) => writer.write_record(&serde_json::to_value(&check)?)?,
+                None => {
+                    println!(
+                        "{}\tscore {}\t{} guesses\t{}",
+                        check.report.password,
+                        check.report.score,
+                        check.report.guesses,
+                        check.report.crack_time
+                    );
+                    if let Some(warning) = &check.warning {
+                        println!("  warning: {}", warning);
+                    }
+                    for suggestion in &check.suggestions {
+                        println!("  suggestion: {}", suggestion);
+                    }
+                    for violation in &check.violations {
+                        println!("  policy: {}", violation);
+                    }
+                }
+            }
+        }
+        if let Some(writer) = writer {
+            writer.finish()?;
+            if matches!(self.format, Some(OutputFormat::Json)) {
+                println!();
+            }
+        }
+
+        if weak > 0 {
+            match (self.min_score, &policy) {
+                (Some(min), Some(_)) => bail!(
+                    "{} of {} passwords scored below {} or broke the policy",
+                    weak,
+                    total,
+                    min
+                ),
+                (Some(min), None) => {
+                    bail!("{} of {} passwords scored below {}", weak, total, min)
+                }
+                _ => bail!("{} of {} passwords broke the policy", weak, total),
+            }
+        }
+        Ok(())
+    }
+}
+
+impl CmdExector for GenPassGenerateOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        let mut passwords = Vec::with_capacity(self.count);
+        let mut entropy = None;
+        if self.passphrase {
+            let content = match &self.wordlist {
+                Some(path) => fs::read_to_string(path)?,
+                None => crate::WORDLIST.to_string(),
+            };
+            let wordlist = crate::parse_wordlist(&content)?;
+            let config: PassphraseConfig = (&self).into();
+            for _ in 0..self.count {
+                passwords.push(crate::process_passphrase(&config, &wordlist)?);
+            }
+            entropy = Some(config.entropy(wordlist.len()));
+        } else if let Some(template) = &self.template {
+            for _ in 0..self.count {
+                passwords.push(crate::process_gentemplate(template));
+            }
+        } else if let Some(policy) = self.policy.load()? {
+            for _ in 0..self.count {
+                passwords.push(crate::process_genpass_policy(&policy)?);
+            }
+        } else {
+            let config: GenPassConfig = (&self).into();
+            for _ in 0..self.count {
+                passwords.push(crate::process_genpass(&config)?);
+            }
+        }
+
+        if let Some(format) = self.format {
+            let headers = StringRecord::from(crate::REPORT_HEADERS.to_vec());
+            let mut writer = crate::record_writer(io::stdout().lock(), format, &headers, b',')?;
+            for password in &passwords {
+                let report = crate::password_report(password)?;
+                writer.write_record(&serde_json::to_value(report)?)?;
+            }
+            writer.finish()?;
+            if matches!(format, OutputFormat::Json) {
+                println!();
+            }
+        } else {
+            for password in &passwords {
+                println!("{}", password);
+            }
+        }
+
+        // output passphrase entropy or password strength in stderr
+        if let Some(entropy) = entropy {
+            eprintln!("Passphrase entropy: {:.1} bits", entropy);
+        } else if let [password] = passwords.as_slice() {
+            let estimate = zxcvbn(password, &[])?;
+            eprintln!("Password strength: {}", estimate.score());
+        }
         Ok(())
     }
 }
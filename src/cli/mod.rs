@@ -1,4 +1,5 @@
 mod base64;
+mod codec;
 mod csv;
 mod genpass;
 mod http;
@@ -10,6 +11,7 @@ use enum_dispatch::enum_dispatch;
 use std::path::{Path, PathBuf};
 
 pub use base64::*;
+pub use codec::*;
 pub use csv::*;
 pub use genpass::*;
 pub use http::*;
@@ -27,11 +29,15 @@ pub struct Opts {
 #[enum_dispatch(CmdExector)]
 pub enum SubCommand {
     #[command(name = "csv", about = "Convert csv to other formats")]
-    Csv(CsvOpts),
+    Csv(Box<CsvOpts>),
     #[command(name = "genpass", about = "Generate password")]
-    GenPass(GenPassOpts),
+    GenPass(Box<GenPassOpts>),
     #[command(subcommand, about = "base64 encode/decode")]
     Base64(Base64SubCommand),
+    #[command(about = "Encode with base64, hex, base32, base58 or url encoding")]
+    Encode(EncodeOpts),
+    #[command(about = "Decode base64, hex, base32, base58 or url encoded data")]
+    Decode(DecodeOpts),
     #[command(subcommand, about = "text sign/verify")]
     Text(TextSubcommand),
     #[command(about = "http server")]
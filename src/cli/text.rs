@@ -1,5 +1,8 @@
 use core::fmt;
-use std::{path::PathBuf, str::FromStr};
+use std::{
+    path::{Path, PathBuf},
+    str::FromStr,
+};
 
 use anyhow::Result;
 use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
@@ -8,8 +11,9 @@ use enum_dispatch::enum_dispatch;
 use tokio::fs;
 
 use crate::{
-    get_content, get_reader, process_text_key_generate, process_text_sign, process_text_verify,
-    verify_file, verify_path, ChaCha20Poly1305DD, CmdExector,
+    get_content, get_reader, process_text_key_generate, process_text_sign, process_text_sign_file,
+    process_text_verify, process_text_verify_file, verify_file, verify_path, ChaCha20Poly1305DD,
+    CmdExector,
 };
 
 #[derive(Debug, Parser)]
@@ -33,6 +37,8 @@ pub struct TextSignOpts {
     pub input: String,
     #[arg(short, long, value_parser = verify_file)]
     pub key: String,
+    /// blake3, ed25519 or ed25519ph. ed25519ph signatures are rcli specific (65 bytes,
+    /// context `rcli-ed25519ph`), other tools won't verify them as is
     #[arg(long, default_value = "blake3", value_parser = parse_text_sign_format)]
     pub format: TextSignFormat,
 }
@@ -45,6 +51,7 @@ pub struct TextVerifyOpts {
     pub key: String,
     #[arg(short, long)]
     pub sig: String,
+    /// blake3, ed25519 or ed25519ph, the ed25519 formats accept either kind of signature
     #[arg(long, default_value = "blake3", value_parser = parse_text_sign_format)]
     pub format: TextSignFormat,
 }
@@ -77,6 +84,10 @@ pub struct TextDecryptOpts {
 pub enum TextSignFormat {
     Blake3,
     Ed25519,
+    /// Ed25519 over the SHA-512 of the input, for inputs too large to hold in memory.
+    /// The signature is rcli specific: a 0x01 byte followed by an RFC 8032 Ed25519ph
+    /// signature with the context `rcli-ed25519ph`, 65 bytes in all.
+    Ed25519ph,
 }
 
 fn parse_text_sign_format(format: &str) -> Result<TextSignFormat, anyhow::Error> {
@@ -90,6 +101,7 @@ impl FromStr for TextSignFormat {
         match s {
             "blake3" => Ok(TextSignFormat::Blake3),
             "ed25519" => Ok(TextSignFormat::Ed25519),
+            "ed25519ph" => Ok(TextSignFormat::Ed25519ph),
             _ => Err(anyhow::anyhow!("Invalid format")),
         }
     }
@@ -100,6 +112,7 @@ impl From<TextSignFormat> for &'static str {
         match format {
             TextSignFormat::Blake3 => "blake3",
             TextSignFormat::Ed25519 => "ed25519",
+            TextSignFormat::Ed25519ph => "ed25519ph",
         }
     }
 }
@@ -112,9 +125,12 @@ impl fmt::Display for TextSignFormat {
 
 impl CmdExector for TextSignOpts {
     async fn execute(self) -> Result<()> {
-        let mut reader = get_reader(&self.input)?;
         let key = get_content(&self.key)?;
-        let sig = process_text_sign(&mut reader, &key, self.format)?;
+        let sig = if self.input == "-" {
+            process_text_sign(&mut get_reader(&self.input)?, &key, self.format)?
+        } else {
+            process_text_sign_file(Path::new(&self.input), &key, self.format)?
+        };
         // base64 output
         let encoded = URL_SAFE_NO_PAD.encode(sig);
         println!("{}", encoded);
@@ -124,10 +140,13 @@ impl CmdExector for TextSignOpts {
 
 impl CmdExector for TextVerifyOpts {
     async fn execute(self) -> Result<()> {
-        let mut reader = get_reader(&self.input)?;
         let key = get_content(&self.key)?;
         let decoded = URL_SAFE_NO_PAD.decode(&self.sig)?;
-        let verified = process_text_verify(&mut reader, &key, &decoded, self.format)?;
+        let verified = if self.input == "-" {
+            process_text_verify(&mut get_reader(&self.input)?, &key, &decoded, self.format)?
+        } else {
+            process_text_verify_file(Path::new(&self.input), &key, &decoded, self.format)?
+        };
         if verified {
             println!("✓ Signature verified");
         } else {
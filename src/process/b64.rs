@@ -1,34 +1,144 @@
 use crate::Base64Format;
-use anyhow::Result;
+use anyhow::{bail, Result};
 use base64::{
-    engine::general_purpose::{STANDARD, URL_SAFE_NO_PAD},
-    Engine,
+    alphabet,
+    engine::{
+        general_purpose::{NO_PAD, PAD},
+        DecodePaddingMode, GeneralPurpose, GeneralPurposeConfig,
+    },
+    read::DecoderReader,
+    write::EncoderWriter,
 };
-use std::io::Read;
-
-pub fn process_encode(reader: &mut dyn Read, format: Base64Format) -> Result<String> {
-    let mut buf = Vec::new();
-    reader.read_to_end(&mut buf)?;
-    let encoded = match format {
-        Base64Format::Standard => STANDARD.encode(&buf),
-        Base64Format::UrlSafe => URL_SAFE_NO_PAD.encode(&buf),
+use std::io::{self, Read, Write};
+
+// decoding accepts input with or without padding, whatever the variant emits
+const PADDED: GeneralPurposeConfig = PAD.with_decode_padding_mode(DecodePaddingMode::Indifferent);
+const UNPADDED: GeneralPurposeConfig =
+    NO_PAD.with_decode_padding_mode(DecodePaddingMode::Indifferent);
+
+const STANDARD: GeneralPurpose = GeneralPurpose::new(&alphabet::STANDARD, PADDED);
+const STANDARD_NO_PAD: GeneralPurpose = GeneralPurpose::new(&alphabet::STANDARD, UNPADDED);
+const URL_SAFE: GeneralPurpose = GeneralPurpose::new(&alphabet::URL_SAFE, PADDED);
+const URL_SAFE_NO_PAD: GeneralPurpose = GeneralPurpose::new(&alphabet::URL_SAFE, UNPADDED);
+const BCRYPT: GeneralPurpose = GeneralPurpose::new(&alphabet::BCRYPT, UNPADDED);
+const CRYPT: GeneralPurpose = GeneralPurpose::new(&alphabet::CRYPT, UNPADDED);
+
+/// Encode everything from the reader in chunks, optionally wrapping lines MIME-style.
+pub fn process_encode(
+    reader: &mut dyn Read,
+    writer: &mut dyn Write,
+    format: Base64Format,
+    wrap: Option<usize>,
+) -> Result<()> {
+    let wrapper = LineWrapper {
+        writer,
+        width: wrap.filter(|w| *w > 0),
+        column: 0,
     };
+    if let Base64Format::Auto = format {
+        bail!("auto only works for decoding, pick a format to encode with");
+    }
+    let mut encoder = EncoderWriter::new(wrapper, engine(format));
+    io::copy(reader, &mut encoder)?;
+    encoder.finish()?.writer.flush()?;
+    Ok(())
+}
 
-    Ok(encoded)
+/// Decode everything from the reader in chunks, whitespace and newlines are ignored.
+///
+/// Padding is optional for every format. `Base64Format::Auto` accepts both the standard
+/// and the url-safe alphabet.
+pub fn process_decode(
+    reader: &mut dyn Read,
+    writer: &mut dyn Write,
+    format: Base64Format,
+) -> Result<()> {
+    let filter = InputFilter {
+        reader,
+        url_safe_to_standard: matches!(format, Base64Format::Auto),
+    };
+    let mut decoder = DecoderReader::new(filter, engine(format));
+    io::copy(&mut decoder, writer)?;
+    writer.flush()?;
+    Ok(())
 }
 
-pub fn process_decode(reader: &mut dyn Read, format: Base64Format) -> Result<String> {
-    let mut buf = String::new();
-    reader.read_to_string(&mut buf)?;
-    // avoid accidental newlines
-    let buf = buf.trim();
+fn engine(format: Base64Format) -> &'static GeneralPurpose {
+    match format {
+        // auto input is mapped onto the standard alphabet
+        Base64Format::Standard | Base64Format::Auto => &STANDARD,
+        Base64Format::StandardNoPad => &STANDARD_NO_PAD,
+        Base64Format::UrlSafe => &URL_SAFE_NO_PAD,
+        Base64Format::UrlSafePad => &URL_SAFE,
+        Base64Format::Bcrypt => &BCRYPT,
+        Base64Format::Crypt => &CRYPT,
+    }
+}
 
-    let decoded = match format {
-        Base64Format::Standard => STANDARD.decode(buf)?,
-        Base64Format::UrlSafe => URL_SAFE_NO_PAD.decode(buf)?,
-    };
-    // TODO: decoded data might not be string (but for this example, we assume it is)
-    Ok(String::from_utf8(decoded)?)
+/// Inserts a newline every `width` bytes.
+struct LineWrapper<'a> {
+    writer: &'a mut dyn Write,
+    width: Option<usize>,
+    column: usize,
+}
+
+impl Write for LineWrapper<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let Some(width) = self.width else {
+            return self.writer.write(buf);
+        };
+        let mut rest = buf;
+        while !rest.is_empty() {
+            // break before the next byte, so the output never ends with a newline
+            if self.column == width {
+                self.writer.write_all(b"\n")?;
+                self.column = 0;
+            }
+            let n = rest.len().min(width - self.column);
+            self.writer.write_all(&rest[..n])?;
+            self.column += n;
+            rest = &rest[n..];
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+/// Drops ascii whitespace, so wrapped or hand-edited input decodes fine.
+struct InputFilter<'a> {
+    reader: &'a mut dyn Read,
+    /// map `-` and `_` onto `+` and `/`, so both alphabets decode with one engine
+    url_safe_to_standard: bool,
+}
+
+impl Read for InputFilter<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            let n = self.reader.read(buf)?;
+            if n == 0 {
+                return Ok(0);
+            }
+            let mut len = 0;
+            for i in 0..n {
+                let b = match buf[i] {
+                    b'-' if self.url_safe_to_standard => b'+',
+                    b'_' if self.url_safe_to_standard => b'/',
+                    b => b,
+                };
+                if !b.is_ascii_whitespace() {
+                    buf[len] = b;
+                    len += 1;
+                }
+            }
+            // a chunk of only whitespace isn't the end of the input
+            if len > 0 {
+                return Ok(len);
+            }
+        }
+    }
 }
 
 #[cfg(test)]
@@ -36,12 +146,24 @@ mod tests {
     use super::*;
     use crate::get_reader;
 
+    fn encode(data: &[u8], format: Base64Format, wrap: Option<usize>) -> Result<String> {
+        let mut buf = Vec::new();
+        process_encode(&mut &data[..], &mut buf, format, wrap)?;
+        Ok(String::from_utf8(buf)?)
+    }
+
+    fn decode(data: &str, format: Base64Format) -> Result<Vec<u8>> {
+        let mut buf = Vec::new();
+        process_decode(&mut data.as_bytes(), &mut buf, format)?;
+        Ok(buf)
+    }
+
     #[test]
     fn test_process_encode() -> Result<()> {
         let input = "Cargo.toml";
         let mut reader = get_reader(input)?;
         let format = Base64Format::Standard;
-        assert!(process_encode(&mut reader, format).is_ok());
+        assert!(process_encode(&mut reader, &mut io::sink(), format, None).is_ok());
         Ok(())
     }
 
@@ -50,8 +172,74 @@ mod tests {
         let input = "fixtures/b64.txt";
         let mut reader = get_reader(input)?;
         let format = Base64Format::UrlSafe;
-        process_decode(&mut reader, format)?;
+        process_decode(&mut reader, &mut io::sink(), format)?;
 
         Ok(())
     }
+
+    #[test]
+    fn test_process_decode_binary() -> Result<()> {
+        let data = [0u8, 159, 146, 150, 255];
+        let encoded = encode(&data, Base64Format::Standard, None)?;
+        assert_eq!(decode(&encoded, Base64Format::Standard)?, data);
+        Ok(())
+    }
+
+    #[test]
+    fn test_encode_wrap() -> Result<()> {
+        let data = vec![7u8; 100_000];
+        let encoded = encode(&data, Base64Format::Standard, Some(76))?;
+        let lines: Vec<_> = encoded.split('\n').collect();
+        assert!(lines[..lines.len() - 1].iter().all(|l| l.len() == 76));
+        assert!(!encoded.ends_with('\n'));
+
+        // 57 bytes encode to exactly one 76 char line, without a trailing newline
+        assert_eq!(
+            encode(&data[..57], Base64Format::Standard, Some(76))?.len(),
+            76
+        );
+
+        assert_eq!(decode(&encoded, Base64Format::Standard)?, data);
+        Ok(())
+    }
+
+    #[test]
+    fn test_decode_skips_whitespace() -> Result<()> {
+        assert_eq!(
+            decode(" aGVs\r\nbG8g\td29y bGQ=\n\n", Base64Format::Standard)?,
+            b"hello world"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_format_variants() -> Result<()> {
+        let data = [0xfbu8, 0xff, 0xbf, 0x01];
+        let cases = [
+            (Base64Format::Standard, "+/+/AQ=="),
+            (Base64Format::StandardNoPad, "+/+/AQ"),
+            (Base64Format::UrlSafe, "-_-_AQ"),
+            (Base64Format::UrlSafePad, "-_-_AQ=="),
+            (Base64Format::Bcrypt, "8989.O"),
+            (Base64Format::Crypt, "yzyz.E"),
+        ];
+        for (format, expected) in cases {
+            assert_eq!(encode(&data, format, None)?, expected, "{}", format);
+            assert_eq!(decode(expected, format)?, data, "{}", format);
+        }
+        assert!(encode(&data, Base64Format::Auto, None).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_decode_padding_and_auto() -> Result<()> {
+        let data = [0xfbu8, 0xff, 0xbf, 0x01];
+        // padded url-safe input, as sent by some identity providers
+        assert_eq!(decode("-_-_AQ==", Base64Format::UrlSafe)?, data);
+        assert_eq!(decode("+/+/AQ", Base64Format::Standard)?, data);
+        for input in ["+/+/AQ==", "+/+/AQ", "-_-_AQ==", "-_-_AQ"] {
+            assert_eq!(decode(input, Base64Format::Auto)?, data, "{}", input);
+        }
+        Ok(())
+    }
 }
@@ -0,0 +1,197 @@
+use std::io::{Read, Write};
+
+use anyhow::{anyhow, bail, Result};
+use serde::Deserialize;
+use serde_json::Value;
+
+use super::b64::{process_decode, process_encode};
+use crate::{Base64Format, DocFormat};
+
+/// Encode or decode the values at `paths` inside json/yaml documents, e.g. a Kubernetes
+/// Secret's `data.*`. Paths are dot separated, `*` matches every key or array element.
+///
+/// Yaml input may hold several `---` separated documents, each one is processed. Decoded
+/// values must be utf-8 text, binary ones are an error naming their key.
+pub fn process_b64_doc(
+    reader: &mut dyn Read,
+    writer: &mut dyn Write,
+    paths: &[String],
+    decode: bool,
+    format: Base64Format,
+    doc_format: DocFormat,
+) -> Result<()> {
+    let mut content = String::new();
+    reader.read_to_string(&mut content)?;
+
+    let mut docs = match doc_format {
+        DocFormat::Json => vec![serde_json::from_str::<Value>(&content)?],
+        DocFormat::Yaml => serde_yaml::Deserializer::from_str(&content)
+            .map(Value::deserialize)
+            .collect::<Result<Vec<_>, _>>()?,
+    };
+
+    let convert = |value: &str| -> Result<String> {
+        let mut buf = Vec::new();
+        if decode {
+            process_decode(&mut value.as_bytes(), &mut buf, format)?;
+            String::from_utf8(buf).map_err(|_| {
+                anyhow!("the decoded value is binary, not utf-8 text, so it can't be stored")
+            })
+        } else {
+            process_encode(&mut value.as_bytes(), &mut buf, format, None)?;
+            Ok(String::from_utf8(buf)?)
+        }
+    };
+
+    for path in paths {
+        let segments: Vec<&str> = path.split('.').collect();
+        let mut matched = 0;
+        for doc in docs.iter_mut() {
+            visit(doc, &segments, "", &mut |key, value| {
+                matched += 1;
+                let text = match value {
+                    Value::Null => return Ok(()),
+                    Value::String(s) => s.clone(),
+                    Value::Bool(_) | Value::Number(_) if !decode => value.to_string(),
+                    _ => bail!("{} is not a string", key),
+                };
+                *value = Value::String(convert(&text).map_err(|e| anyhow!("{}: {}", key, e))?);
+                Ok(())
+            })?;
+        }
+        if matched == 0 {
+            bail!("Nothing matches the path {:?}", path);
+        }
+    }
+
+    match doc_format {
+        DocFormat::Json => writeln!(writer, "{}", serde_json::to_string_pretty(&docs[0])?)?,
+        DocFormat::Yaml => {
+            for (i, doc) in docs.iter().enumerate() {
+                if i > 0 {
+                    writeln!(writer, "---")?;
+                }
+                write!(writer, "{}", serde_yaml::to_string(doc)?)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Call `f` with every value matching `path`, and its key with the wildcards filled in.
+fn visit(
+    value: &mut Value,
+    path: &[&str],
+    key: &str,
+    f: &mut dyn FnMut(&str, &mut Value) -> Result<()>,
+) -> Result<()> {
+    let Some((segment, rest)) = path.split_first() else {
+        return f(key, value);
+    };
+    let child_key = |k: &str| {
+        if key.is_empty() {
+            k.to_string()
+        } else {
+            format!("{}.{}", key, k)
+        }
+    };
+    match value {
+        Value::Object(map) if *segment == "*" => {
+            for (k, child) in map.iter_mut() {
+                visit(child, rest, &child_key(k), f)?;
+            }
+        }
+        Value::Object(map) => {
+            if let Some(child) = map.get_mut(*segment) {
+                visit(child, rest, &child_key(segment), f)?;
+            }
+        }
+        Value::Array(values) if *segment == "*" => {
+            for (i, child) in values.iter_mut().enumerate() {
+                visit(child, rest, &child_key(&i.to_string()), f)?;
+            }
+        }
+        Value::Array(values) => {
+            if let Some(child) = segment
+                .parse::<usize>()
+                .ok()
+                .and_then(|i| values.get_mut(i))
+            {
+                visit(child, rest, &child_key(segment), f)?;
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECRET: &str = "apiVersion: v1
+kind: Secret
+metadata:
+  name: db
+data:
+  username: YWRtaW4=
+  password: c2VjcmV0
+";
+
+    fn run(input: &str, paths: &[&str], decode: bool, doc_format: DocFormat) -> Result<String> {
+        let paths: Vec<String> = paths.iter().map(|p| p.to_string()).collect();
+        let mut output = Vec::new();
+        process_b64_doc(
+            &mut input.as_bytes(),
+            &mut output,
+            &paths,
+            decode,
+            Base64Format::Standard,
+            doc_format,
+        )?;
+        Ok(String::from_utf8(output)?)
+    }
+
+    #[test]
+    fn test_decode_and_encode_secret() -> Result<()> {
+        let decoded = run(SECRET, &["data.*"], true, DocFormat::Yaml)?;
+        assert!(decoded.contains("username: admin\n  password: secret\n"));
+        assert!(decoded.contains("name: db\n"));
+        assert_eq!(run(&decoded, &["data.*"], false, DocFormat::Yaml)?, SECRET);
+        Ok(())
+    }
+
+    #[test]
+    fn test_json_paths() -> Result<()> {
+        let input = r#"{"items": [{"token": "abc"}, {"token": "xyz"}], "port": 80}"#;
+        let output = run(input, &["items.*.token", "port"], false, DocFormat::Json)?;
+        let value: Value = serde_json::from_str(&output)?;
+        assert_eq!(value["items"][0]["token"], "YWJj");
+        assert_eq!(value["items"][1]["token"], "eHl6");
+        assert_eq!(value["port"], "ODA=");
+
+        assert!(run(input, &["items.0"], false, DocFormat::Json).is_err());
+        assert!(run(input, &["missing"], false, DocFormat::Json).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_decode_binary_value() {
+        // 0xff 0xfe isn't utf-8
+        let input = "data:\n  text: YQ==\n  keystore: //4=\n";
+        let err = run(input, &["data.*"], true, DocFormat::Yaml)
+            .unwrap_err()
+            .to_string();
+        assert!(err.starts_with("data.keystore: "), "{}", err);
+    }
+
+    #[test]
+    fn test_multiple_yaml_documents() -> Result<()> {
+        let input = "data:\n  a: YQ==\n---\ndata:\n  b: Yg==\n";
+        assert_eq!(
+            run(input, &["data.*"], true, DocFormat::Yaml)?,
+            "data:\n  a: a\n---\ndata:\n  b: b\n"
+        );
+        Ok(())
+    }
+}
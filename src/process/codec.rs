@@ -0,0 +1,328 @@
+use std::io::{ErrorKind, Read, Write};
+
+use anyhow::Result;
+use data_encoding::{BASE32, HEXLOWER, HEXLOWER_PERMISSIVE};
+use percent_encoding::{percent_decode, percent_encode, AsciiSet, NON_ALPHANUMERIC};
+
+use super::b64::{process_decode, process_encode};
+use crate::{Base64Format, CodecFormat};
+
+/// Everything but the RFC 3986 unreserved characters gets percent-encoded.
+const URL_ENCODE_SET: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'.')
+    .remove(b'_')
+    .remove(b'~');
+
+/// Bytes read at a time by the streaming codecs, a multiple of the hex (1) and base32 (5)
+/// input block sizes so only the last chunk can end in a partial block.
+const CHUNK: usize = 5 * 8 * 1024;
+
+pub trait Codec {
+    // encode everything from the reader into text
+    fn encode(&self, reader: &mut dyn Read, writer: &mut dyn Write) -> Result<()>;
+    // decode text from the reader into the original bytes
+    fn decode(&self, reader: &mut dyn Read, writer: &mut dyn Write) -> Result<()>;
+}
+
+pub struct Base64Codec {
+    format: Base64Format,
+    wrap: Option<usize>,
+}
+
+pub struct HexCodec;
+
+/// RFC 4648 base32, padded.
+pub struct Base32Codec;
+
+/// Bitcoin alphabet. Base58 isn't block based, so the whole input is read into memory.
+pub struct Base58Codec;
+
+/// Percent-encoding as used in urls, reads the whole input into memory.
+pub struct UrlCodec;
+
+impl Base64Codec {
+    pub fn new(format: Base64Format, wrap: Option<usize>) -> Self {
+        Self { format, wrap }
+    }
+}
+
+impl Codec for Base64Codec {
+    fn encode(&self, reader: &mut dyn Read, writer: &mut dyn Write) -> Result<()> {
+        process_encode(reader, writer, self.format, self.wrap)
+    }
+
+    fn decode(&self, reader: &mut dyn Read, writer: &mut dyn Write) -> Result<()> {
+        process_decode(reader, writer, self.format)
+    }
+}
+
+impl Codec for HexCodec {
+    fn encode(&self, reader: &mut dyn Read, writer: &mut dyn Write) -> Result<()> {
+        encode_chunks(reader, writer, |chunk| HEXLOWER.encode(chunk))
+    }
+
+    fn decode(&self, reader: &mut dyn Read, writer: &mut dyn Write) -> Result<()> {
+        // accepts upper case too
+        decode_chunks(reader, writer, 2, |chunk| {
+            Ok(HEXLOWER_PERMISSIVE.decode(chunk)?)
+        })
+    }
+}
+
+impl Codec for Base32Codec {
+    fn encode(&self, reader: &mut dyn Read, writer: &mut dyn Write) -> Result<()> {
+        encode_chunks(reader, writer, |chunk| BASE32.encode(chunk))
+    }
+
+    fn decode(&self, reader: &mut dyn Read, writer: &mut dyn Write) -> Result<()> {
+        decode_chunks(reader, writer, 8, |chunk| Ok(BASE32.decode(chunk)?))
+    }
+}
+
+impl Codec for Base58Codec {
+    fn encode(&self, reader: &mut dyn Read, writer: &mut dyn Write) -> Result<()> {
+        writer.write_all(bs58::encode(read_all(reader)?).into_string().as_bytes())?;
+        Ok(())
+    }
+
+    fn decode(&self, reader: &mut dyn Read, writer: &mut dyn Write) -> Result<()> {
+        writer.write_all(&bs58::decode(read_text(reader)?).into_vec()?)?;
+        Ok(())
+    }
+}
+
+impl Codec for UrlCodec {
+    fn encode(&self, reader: &mut dyn Read, writer: &mut dyn Write) -> Result<()> {
+        let buf = read_all(reader)?;
+        for part in percent_encode(&buf, URL_ENCODE_SET) {
+            writer.write_all(part.as_bytes())?;
+        }
+        Ok(())
+    }
+
+    fn decode(&self, reader: &mut dyn Read, writer: &mut dyn Write) -> Result<()> {
+        let buf = read_all(reader)?;
+        // avoid accidental newlines
+        let buf = buf.trim_ascii_end();
+        writer.write_all(&percent_decode(buf).collect::<Vec<u8>>())?;
+        Ok(())
+    }
+}
+
+/// Encode `CHUNK` bytes at a time, every chunk but the last is full.
+fn encode_chunks(
+    reader: &mut dyn Read,
+    writer: &mut dyn Write,
+    encode: impl Fn(&[u8]) -> String,
+) -> Result<()> {
+    let mut buf = vec![0; CHUNK];
+    loop {
+        let n = read_full(reader, &mut buf)?;
+        if n > 0 {
+            writer.write_all(encode(&buf[..n]).as_bytes())?;
+        }
+        if n < CHUNK {
+            break;
+        }
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// Decode whole blocks of `block` encoded characters as they arrive, whitespace and
+/// newlines are dropped. A partial block left at the end is passed to `decode` to report.
+fn decode_chunks(
+    reader: &mut dyn Read,
+    writer: &mut dyn Write,
+    block: usize,
+    decode: impl Fn(&[u8]) -> Result<Vec<u8>>,
+) -> Result<()> {
+    let mut buf = vec![0; CHUNK];
+    let mut pending = Vec::with_capacity(CHUNK + block);
+    loop {
+        let n = match reader.read(&mut buf) {
+            Ok(n) => n,
+            Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e.into()),
+        };
+        pending.extend(buf[..n].iter().filter(|b| !b.is_ascii_whitespace()));
+        let aligned = if n == 0 {
+            pending.len()
+        } else {
+            pending.len() / block * block
+        };
+        if aligned > 0 {
+            writer.write_all(&decode(&pending[..aligned])?)?;
+            pending.drain(..aligned);
+        }
+        if n == 0 {
+            break;
+        }
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// Fill `buf` unless the reader runs out first, returns how much was read.
+fn read_full(reader: &mut dyn Read, buf: &mut [u8]) -> Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..]) {
+            Ok(0) => break,
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == ErrorKind::Interrupted => {}
+            Err(e) => return Err(e.into()),
+        }
+    }
+    Ok(filled)
+}
+
+fn read_all(reader: &mut dyn Read) -> Result<Vec<u8>> {
+    let mut buf = Vec::new();
+    reader.read_to_end(&mut buf)?;
+    Ok(buf)
+}
+
+/// Read encoded input, dropping whitespace and newlines.
+fn read_text(reader: &mut dyn Read) -> Result<Vec<u8>> {
+    let mut buf = read_all(reader)?;
+    buf.retain(|b| !b.is_ascii_whitespace());
+    Ok(buf)
+}
+
+fn codec(format: CodecFormat, base64: Base64Format, wrap: Option<usize>) -> Box<dyn Codec> {
+    match format {
+        CodecFormat::Base64 => Box::new(Base64Codec::new(base64, wrap)),
+        CodecFormat::Hex => Box::new(HexCodec),
+        CodecFormat::Base32 => Box::new(Base32Codec),
+        CodecFormat::Base58 => Box::new(Base58Codec),
+        CodecFormat::Url => Box::new(UrlCodec),
+    }
+}
+
+/// `base64` and `wrap` only apply to the base64 codec.
+pub fn process_codec_encode(
+    reader: &mut dyn Read,
+    writer: &mut dyn Write,
+    format: CodecFormat,
+    base64: Base64Format,
+    wrap: Option<usize>,
+) -> Result<()> {
+    codec(format, base64, wrap).encode(reader, writer)
+}
+
+pub fn process_codec_decode(
+    reader: &mut dyn Read,
+    writer: &mut dyn Write,
+    format: CodecFormat,
+    base64: Base64Format,
+) -> Result<()> {
+    codec(format, base64, None).decode(reader, writer)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(format: CodecFormat, data: &[u8]) -> Result<String> {
+        let mut encoded = Vec::new();
+        process_codec_encode(
+            &mut &data[..],
+            &mut encoded,
+            format,
+            Base64Format::Standard,
+            None,
+        )?;
+        let mut decoded = Vec::new();
+        process_codec_decode(
+            &mut &encoded[..],
+            &mut decoded,
+            format,
+            Base64Format::Standard,
+        )?;
+        assert_eq!(decoded, data, "{}", format);
+        Ok(String::from_utf8(encoded)?)
+    }
+
+    #[test]
+    fn test_codec_round_trip() -> Result<()> {
+        let binary: Vec<u8> = (0..=255).collect();
+        for format in [
+            CodecFormat::Base64,
+            CodecFormat::Hex,
+            CodecFormat::Base32,
+            CodecFormat::Base58,
+            CodecFormat::Url,
+        ] {
+            round_trip(format, b"")?;
+            round_trip(format, b"hello world")?;
+            round_trip(format, "a/b?c=d&e=ü".as_bytes())?;
+            round_trip(format, &binary)?;
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_codec_encoded_values() -> Result<()> {
+        assert_eq!(round_trip(CodecFormat::Hex, b"hi!")?, "686921");
+        assert_eq!(round_trip(CodecFormat::Base32, b"hi!")?, "NBUSC===");
+        assert_eq!(round_trip(CodecFormat::Base58, b"hi!")?, "c55r");
+        assert_eq!(round_trip(CodecFormat::Url, b"a b/c~")?, "a%20b%2Fc~");
+        assert_eq!(round_trip(CodecFormat::Base64, b"hi!")?, "aGkh");
+        Ok(())
+    }
+
+    #[test]
+    fn test_codec_decode_lenient_input() -> Result<()> {
+        let mut decoded = Vec::new();
+        process_codec_decode(
+            &mut "68 69\n21\n".as_bytes(),
+            &mut decoded,
+            CodecFormat::Hex,
+            Base64Format::Standard,
+        )?;
+        assert_eq!(decoded, b"hi!");
+
+        let err = process_codec_decode(
+            &mut "686".as_bytes(),
+            &mut Vec::new(),
+            CodecFormat::Hex,
+            Base64Format::Standard,
+        );
+        assert!(err.is_err());
+        Ok(())
+    }
+
+    /// Hands out at most 7 bytes per read, so blocks straddle reads.
+    struct Trickle<'a>(&'a [u8]);
+
+    impl Read for Trickle<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            let n = buf.len().min(self.0.len()).min(7);
+            buf[..n].copy_from_slice(&self.0[..n]);
+            self.0 = &self.0[n..];
+            Ok(n)
+        }
+    }
+
+    #[test]
+    fn test_codec_streams_in_chunks() -> Result<()> {
+        let data: Vec<u8> = (0..CHUNK * 2 + 3).map(|i| (i * 7) as u8).collect();
+        for format in [CodecFormat::Hex, CodecFormat::Base32] {
+            let mut encoded = Vec::new();
+            let codec = codec(format, Base64Format::Standard, None);
+            codec.encode(&mut Trickle(&data), &mut encoded)?;
+            let expected = match format {
+                CodecFormat::Hex => HEXLOWER.encode(&data),
+                _ => BASE32.encode(&data),
+            };
+            assert_eq!(String::from_utf8(encoded.clone())?, expected);
+
+            let mut decoded = Vec::new();
+            codec.decode(&mut Trickle(&encoded), &mut decoded)?;
+            assert_eq!(decoded, data, "{}", format);
+        }
+        Ok(())
+    }
+}
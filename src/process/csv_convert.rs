@@ -1,32 +1,564 @@
-use std::fs;
+use std::io::{BufWriter, Read, Write};
 
-use anyhow::Result;
-use csv::Reader;
+use anyhow::{anyhow, bail, Result};
+use csv::{Reader, ReaderBuilder, StringRecord, WriterBuilder};
+use serde_json::{Map, Value};
 
-use crate::OutputFormat;
+use super::{
+    csv_filter::{ColumnSelection, Filter},
+    csv_nested::NestedLayout,
+    csv_schema::{infer_value, ColumnType, CsvSchema},
+    csv_writer::record_writer,
+};
+use crate::{LenientMode, OutputFormat};
 
-pub fn process_csv(input: &str, output: String, format: OutputFormat) -> Result<()> {
-    let mut reader = Reader::from_path(input)?;
-    let mut ret = Vec::with_capacity(128);
-    let headers = reader.headers()?.clone();
-    for result in reader.records() {
-        let record = result?;
+/// How the input csv should be parsed.
+#[derive(Debug, Clone)]
+pub struct CsvReadOptions {
+    pub delimiter: u8,
+    pub has_headers: bool,
+    /// column names to use instead of the header row (or the synthetic `col0`, `col1`, ...)
+    pub columns: Option<Vec<String>>,
+}
+
+impl Default for CsvReadOptions {
+    fn default() -> Self {
+        Self {
+            delimiter: b',',
+            has_headers: true,
+            columns: None,
+        }
+    }
+}
+
+impl CsvReadOptions {
+    pub fn reader<R: Read>(&self, rdr: R) -> Reader<R> {
+        // field counts are checked by us, so errors can point at the header and the record
+        ReaderBuilder::new()
+            .delimiter(self.delimiter)
+            .has_headers(self.has_headers)
+            .flexible(true)
+            .from_reader(rdr)
+    }
+
+    /// Resolve the column names for the given reader.
+    pub fn headers<R: Read>(&self, reader: &mut Reader<R>) -> Result<StringRecord> {
+        // without a header row, the reader returns the first record (not consumed)
+        let first = reader.headers()?;
+        match &self.columns {
+            Some(columns) => {
+                if columns.len() != first.len() {
+                    bail!(
+                        "{} column names given, but the csv has {} columns",
+                        columns.len(),
+                        first.len()
+                    );
+                }
+                Ok(StringRecord::from(columns.clone()))
+            }
+            None if self.has_headers => Ok(first.clone()),
+            None => Ok((0..first.len()).map(|i| format!("col{}", i)).collect()),
+        }
+    }
+
+    /// Error unless the record has as many fields as the header.
+    pub fn check_record(&self, headers: &StringRecord, record: &StringRecord) -> Result<()> {
+        if record.len() != headers.len() {
+            bail!(
+                "line {}: expected {} fields as in the header, found {}: {:?}",
+                record_line(record),
+                headers.len(),
+                record.len(),
+                record.iter().collect::<Vec<_>>()
+            );
+        }
+        Ok(())
+    }
+}
+
+fn record_line(record: &StringRecord) -> u64 {
+    record.position().map(|p| p.line()).unwrap_or_default()
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct CsvConvertOptions {
+    pub read: CsvReadOptions,
+    /// turn numbers, booleans and empty cells into native values
+    pub infer: bool,
+    /// pinned column types, values that don't match are an error
+    pub schema: Option<CsvSchema>,
+    /// delimiter for csv output, defaults to the input delimiter
+    pub output_delimiter: Option<u8>,
+    pub selection: ColumnSelection,
+    /// only rows matching the filter are written, evaluated before columns are selected
+    pub filter: Option<Filter>,
+    /// build nested objects/arrays from headers like `address.city` and `tags[0]`
+    pub nested: bool,
+    /// keep going on malformed rows instead of failing
+    pub lenient: Option<LenientMode>,
+}
+
+/// How many rejected line numbers `CsvReport` keeps, the rows themselves go to the rejects writer.
+const REPORTED_LINES: usize = 10;
+
+/// What happened during a conversion.
+#[derive(Debug, Clone, Default)]
+pub struct CsvReport {
+    pub written: usize,
+    /// short rows padded with empty cells in `LenientMode::Pad`
+    pub padded: usize,
+    /// rows dropped in lenient mode
+    pub rejected: usize,
+    /// lines of the first few dropped rows
+    pub rejected_lines: Vec<u64>,
+}
+
+/// Converts csv records into json objects, typing each cell according to the options.
+struct RecordConverter {
+    headers: StringRecord,
+    types: Vec<Option<ColumnType>>,
+    infer: bool,
+    filter: Option<Filter>,
+    /// (index into the headers, output name)
+    columns: Vec<(usize, String)>,
+    nested: Option<NestedLayout>,
+}
+
+impl RecordConverter {
+    fn try_new(headers: StringRecord, opts: &CsvConvertOptions) -> Result<Self> {
+        let types = match &opts.schema {
+            Some(schema) => schema.column_types(headers.iter())?,
+            None => vec![None; headers.len()],
+        };
+        if let Some(filter) = &opts.filter {
+            if let Some(c) = filter
+                .columns()
+                .into_iter()
+                .find(|c| !headers.iter().any(|h| h == *c))
+            {
+                bail!("Unknown column {:?} in filter", c);
+            }
+        }
+        let columns = opts.selection.resolve(headers.iter())?;
+        let nested = if opts.nested {
+            Some(NestedLayout::try_new(
+                columns.iter().map(|(_, name)| name.as_str()),
+            )?)
+        } else {
+            None
+        };
+        Ok(Self {
+            headers,
+            types,
+            infer: opts.infer,
+            filter: opts.filter.clone(),
+            columns,
+            nested,
+        })
+    }
+
+    /// Column names of the converted rows.
+    fn output_headers(&self) -> StringRecord {
+        self.columns.iter().map(|(_, name)| name.as_str()).collect()
+    }
+
+    /// Convert a record, or `None` if it's filtered out.
+    fn convert(&self, record: &StringRecord) -> Result<Option<Value>> {
         // headers.iter() -> 使用 headers 的迭代器
         // record.iter() -> 使用 record 的迭代器
         // zip() -> 将两个迭代器合并为一个元组的迭代器 [(header, record), ..]
-        // collect::<Value>() -> 将元组的迭代器转换为 JSON Value
-        let json_value = headers
+        let mut row = Map::with_capacity(self.headers.len());
+        for ((header, cell), ty) in self.headers.iter().zip(record.iter()).zip(&self.types) {
+            let value = match ty {
+                Some(ty) => ty.convert(cell).map_err(|e| {
+                    anyhow!("line {}, column {:?}: {}", record_line(record), header, e)
+                })?,
+                None if self.infer => infer_value(cell),
+                None => Value::String(cell.to_string()),
+            };
+            row.insert(header.to_string(), value);
+        }
+        if let Some(filter) = &self.filter {
+            if !filter.matches(&row) {
+                return Ok(None);
+            }
+        }
+        let cells = self
+            .columns
             .iter()
-            .zip(record.iter())
-            .collect::<serde_json::Value>();
+            .map(|(i, _)| row[&self.headers[*i]].clone());
+        let row = match &self.nested {
+            Some(layout) => layout.build(cells)?,
+            None => Value::Object(
+                self.columns
+                    .iter()
+                    .map(|(_, name)| name.clone())
+                    .zip(cells)
+                    .collect(),
+            ),
+        };
+        Ok(Some(row))
+    }
+}
 
-        ret.push(json_value);
+/// Convert csv to `format`. In lenient mode dropped rows are written to `rejects` as they
+/// happen, as csv with their line and the reason in front of the original fields.
+pub fn process_csv(
+    reader: &mut dyn Read,
+    writer: &mut dyn Write,
+    rejects: Option<&mut dyn Write>,
+    format: OutputFormat,
+    opts: &CsvConvertOptions,
+) -> Result<CsvReport> {
+    if opts.nested && matches!(format, OutputFormat::Csv) {
+        bail!("Nested output isn't supported for csv");
     }
+    let mut reader = opts.read.reader(reader);
+    let headers = opts.read.headers(&mut reader)?;
+    let converter = RecordConverter::try_new(headers, opts)?;
 
-    let content = match format {
-        OutputFormat::Json => serde_json::to_string_pretty(&ret)?,
-        OutputFormat::Yaml => serde_yaml::to_string(&ret)?,
+    let delimiter = opts.output_delimiter.unwrap_or(opts.read.delimiter);
+    let headers = converter.output_headers();
+    let mut writer = record_writer(BufWriter::new(writer), format, &headers, delimiter)?;
+    let mut rejects = match rejects {
+        Some(rejects) => {
+            let mut rejects = WriterBuilder::new().flexible(true).from_writer(rejects);
+            rejects.write_record(["line", "reason"])?;
+            Some(rejects)
+        }
+        None => None,
     };
-    fs::write(output, content)?;
-    Ok(())
+    let mut report = CsvReport::default();
+    let mut reject = |report: &mut CsvReport, line: u64, reason: String, record: &StringRecord| {
+        report.rejected += 1;
+        if report.rejected_lines.len() < REPORTED_LINES {
+            report.rejected_lines.push(line);
+        }
+        if let Some(rejects) = rejects.as_mut() {
+            let line = line.to_string();
+            let fields = [line.as_str(), reason.as_str()];
+            rejects.write_record(fields.into_iter().chain(record.iter()))?;
+        }
+        Ok::<_, anyhow::Error>(())
+    };
+    // reuse one record buffer, only the current row is held in memory
+    let mut record = StringRecord::new();
+    loop {
+        let line = reader.position().line();
+        match reader.read_record(&mut record) {
+            Ok(true) => {}
+            Ok(false) => break,
+            // a bad row is skipped, but an io error means the rest of the input is gone too
+            Err(e)
+                if opts.lenient.is_some()
+                    && matches!(
+                        e.kind(),
+                        csv::ErrorKind::Utf8 { .. } | csv::ErrorKind::UnequalLengths { .. }
+                    ) =>
+            {
+                reject(&mut report, line, e.to_string(), &StringRecord::new())?;
+                continue;
+            }
+            Err(e) => return Err(e.into()),
+        }
+        let line = record.position().map_or(line, |p| p.line());
+
+        if let Err(e) = opts.read.check_record(&converter.headers, &record) {
+            match opts.lenient {
+                Some(LenientMode::Pad) if record.len() < converter.headers.len() => {
+                    while record.len() < converter.headers.len() {
+                        record.push_field("");
+                    }
+                    report.padded += 1;
+                }
+                Some(_) => {
+                    reject(&mut report, line, e.to_string(), &record)?;
+                    continue;
+                }
+                None => return Err(e),
+            }
+        }
+
+        match converter.convert(&record) {
+            Ok(Some(row)) => {
+                writer.write_record(&row)?;
+                report.written += 1;
+            }
+            Ok(None) => {}
+            Err(e) if opts.lenient.is_some() => reject(&mut report, line, e.to_string(), &record)?,
+            Err(e) => return Err(e),
+        }
+    }
+    writer.finish()?;
+    if let Some(mut rejects) = rejects {
+        rejects.flush()?;
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::{
+        fs::File,
+        io::{BufRead, BufReader},
+    };
+
+    fn read_all(data: &str, opts: &CsvReadOptions) -> Result<(StringRecord, Vec<StringRecord>)> {
+        let mut reader = opts.reader(data.as_bytes());
+        let headers = opts.headers(&mut reader)?;
+        let records = reader.records().collect::<Result<Vec<_>, _>>()?;
+        Ok((headers, records))
+    }
+
+    #[test]
+    fn test_read_with_delimiter() -> Result<()> {
+        let opts = CsvReadOptions {
+            delimiter: b'\t',
+            ..Default::default()
+        };
+        let (headers, records) = read_all("name\tage\nalice\t30\n", &opts)?;
+        assert_eq!(headers, vec!["name", "age"]);
+        assert_eq!(records[0], vec!["alice", "30"]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_headerless_synthetic_columns() -> Result<()> {
+        let opts = CsvReadOptions {
+            delimiter: b';',
+            has_headers: false,
+            columns: None,
+        };
+        let (headers, records) = read_all("alice;30\nbob;25\n", &opts)?;
+        assert_eq!(headers, vec!["col0", "col1"]);
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0], vec!["alice", "30"]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_headerless_named_columns() -> Result<()> {
+        let opts = CsvReadOptions {
+            has_headers: false,
+            columns: Some(vec!["name".into(), "age".into()]),
+            ..Default::default()
+        };
+        let (headers, records) = read_all("alice,30\n", &opts)?;
+        assert_eq!(headers, vec!["name", "age"]);
+        assert_eq!(records.len(), 1);
+
+        let opts = CsvReadOptions {
+            has_headers: false,
+            columns: Some(vec!["name".into()]),
+            ..Default::default()
+        };
+        assert!(read_all("alice,30\n", &opts).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_convert_with_schema() -> Result<()> {
+        let schema: CsvSchema = serde_yaml::from_str("Kit Number: integer")?;
+        let opts = CsvConvertOptions {
+            infer: true,
+            schema: Some(schema),
+            ..Default::default()
+        };
+        let (headers, records) = read_all(
+            "Name,Kit Number,Active\nBuffon,77,true\nPerin,,false\nX,nine,\n",
+            &opts.read,
+        )?;
+        let converter = RecordConverter::try_new(headers, &opts)?;
+        assert_eq!(
+            converter.convert(&records[0])?,
+            Some(json!({"Name": "Buffon", "Kit Number": 77, "Active": true}))
+        );
+        assert_eq!(
+            converter.convert(&records[1])?,
+            Some(json!({"Name": "Perin", "Kit Number": null, "Active": false}))
+        );
+        let err = converter.convert(&records[2]).unwrap_err();
+        assert!(err.to_string().contains("line 4"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_convert_with_selection_and_filter() -> Result<()> {
+        let opts = CsvConvertOptions {
+            selection: ColumnSelection {
+                exclude: vec!["DOB".into()],
+                rename: vec![("Kit Number".into(), "kit_number".into())],
+                ..Default::default()
+            },
+            filter: Some(r#"Nationality == "Italy""#.parse()?),
+            ..Default::default()
+        };
+        let (headers, records) = read_all(
+            "Name,DOB,Nationality,Kit Number\nSzczesny,1990,Poland,1\nPerin,1992,Italy,37\n",
+            &opts.read,
+        )?;
+        let converter = RecordConverter::try_new(headers, &opts)?;
+        assert_eq!(
+            converter.output_headers(),
+            vec!["Name", "Nationality", "kit_number"]
+        );
+        assert_eq!(converter.convert(&records[0])?, None);
+        assert_eq!(
+            converter.convert(&records[1])?,
+            Some(json!({"Name": "Perin", "Nationality": "Italy", "kit_number": "37"}))
+        );
+
+        let opts = CsvConvertOptions {
+            filter: Some("Email == 'x'".parse()?),
+            ..Default::default()
+        };
+        assert!(RecordConverter::try_new(converter.headers.clone(), &opts).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_convert_nested() -> Result<()> {
+        let opts = CsvConvertOptions {
+            infer: true,
+            nested: true,
+            ..Default::default()
+        };
+        let (headers, records) = read_all(
+            "name,address.city,tags[0],tags[1]\na,Turin,1,2\n",
+            &opts.read,
+        )?;
+        let converter = RecordConverter::try_new(headers, &opts)?;
+        assert_eq!(
+            converter.convert(&records[0])?,
+            Some(json!({"name": "a", "address": {"city": "Turin"}, "tags": [1, 2]}))
+        );
+
+        let (headers, _) = read_all("address,address.city\n", &opts.read)?;
+        assert!(RecordConverter::try_new(headers, &opts).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_malformed_rows() -> Result<()> {
+        let input = "name,age\nalice,30\nbob\ncarol,20,extra\ndave,x\n";
+        let schema: CsvSchema = serde_yaml::from_str("age: integer")?;
+        let convert = |lenient| -> Result<(CsvReport, String, String)> {
+            let opts = CsvConvertOptions {
+                schema: Some(schema.clone()),
+                lenient,
+                ..Default::default()
+            };
+            let mut output = Vec::new();
+            let mut rejects = Vec::new();
+            let report = process_csv(
+                &mut input.as_bytes(),
+                &mut output,
+                Some(&mut rejects),
+                OutputFormat::Ndjson,
+                &opts,
+            )?;
+            Ok((
+                report,
+                String::from_utf8(output)?,
+                String::from_utf8(rejects)?,
+            ))
+        };
+
+        let err = convert(None).unwrap_err().to_string();
+        assert_eq!(
+            err,
+            r#"line 3: expected 2 fields as in the header, found 1: ["bob"]"#
+        );
+
+        let (report, output, rejects) = convert(Some(LenientMode::Skip))?;
+        assert_eq!(output, "{\"name\":\"alice\",\"age\":30}\n");
+        assert_eq!(report.written, 1);
+        assert_eq!(report.rejected, 3);
+        assert_eq!(report.rejected_lines, vec![3, 4, 5]);
+        let lines = rejects.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 4);
+        assert_eq!(lines[0], "line,reason");
+        assert!(lines[1].starts_with("3,") && lines[1].ends_with(",bob"));
+        assert!(lines[3].starts_with("5,") && lines[3].ends_with(",dave,x"));
+
+        let (report, output, _) = convert(Some(LenientMode::Pad))?;
+        assert_eq!(
+            output,
+            "{\"name\":\"alice\",\"age\":30}\n{\"name\":\"bob\",\"age\":null}\n"
+        );
+        assert_eq!(report.padded, 1);
+        assert_eq!(report.rejected, 2);
+        Ok(())
+    }
+
+    #[test]
+    fn test_lenient_keeps_io_errors() {
+        struct Broken(bool);
+        impl Read for Broken {
+            fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+                if std::mem::replace(&mut self.0, true) {
+                    return Err(std::io::Error::other("disk on fire"));
+                }
+                let header = b"name,age\n";
+                buf[..header.len()].copy_from_slice(header);
+                Ok(header.len())
+            }
+        }
+        let opts = CsvConvertOptions {
+            lenient: Some(LenientMode::Skip),
+            ..Default::default()
+        };
+        let err = process_csv(
+            &mut Broken(false),
+            &mut Vec::new(),
+            None,
+            OutputFormat::Json,
+            &opts,
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("disk on fire"), "{}", err);
+    }
+
+    #[test]
+    fn test_process_large_csv() -> Result<()> {
+        const ROWS: usize = 200_000;
+        let dir = std::env::temp_dir();
+        let input = dir.join(format!("rcli-large-{}.csv", std::process::id()));
+        let output = dir.join(format!("rcli-large-{}.ndjson", std::process::id()));
+
+        let mut writer = BufWriter::new(File::create(&input)?);
+        writeln!(writer, "id,name,score,active")?;
+        for i in 0..ROWS {
+            writeln!(writer, "{},user{},{}.5,{}", i, i, i % 100, i % 2 == 0)?;
+        }
+        writer.flush()?;
+        drop(writer);
+
+        let opts = CsvConvertOptions {
+            infer: true,
+            ..Default::default()
+        };
+        process_csv(
+            &mut File::open(&input)?,
+            &mut File::create(&output)?,
+            None,
+            OutputFormat::Ndjson,
+            &opts,
+        )?;
+
+        let reader = BufReader::new(File::open(&output)?);
+        let mut count = 0;
+        for line in reader.lines() {
+            let row: Value = serde_json::from_str(&line?)?;
+            assert_eq!(row["id"], json!(count));
+            count += 1;
+        }
+        assert_eq!(count, ROWS);
+
+        std::fs::remove_file(input)?;
+        std::fs::remove_file(output)?;
+        Ok(())
+    }
 }
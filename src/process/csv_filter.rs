@@ -0,0 +1,458 @@
+use std::{cmp::Ordering, fmt, str::FromStr};
+
+use anyhow::{anyhow, bail, Result};
+use serde_json::{Map, Value};
+
+use super::csv_writer::cell_to_string;
+
+/// A row filter for `--where`, e.g. `Nationality == "Italy" && (Kit Number > 10 || !active)`.
+///
+/// Columns are bare identifiers (`Position`, `address.city`) or quoted with backticks when
+/// they contain spaces (`` `Kit Number` ``). Literals are strings in single or double quotes,
+/// numbers, `true`, `false` and `null`. Comparisons are numeric when both sides are numbers
+/// (or numeric strings), otherwise the text is compared.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Filter {
+    Compare(Operand, CompareOp, Operand),
+    /// a bare column is true unless it's null, empty, `false` or `0`
+    Truthy(Operand),
+    Not(Box<Filter>),
+    And(Box<Filter>, Box<Filter>),
+    Or(Box<Filter>, Box<Filter>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Operand {
+    Column(String),
+    Literal(Value),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompareOp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+/// Which columns end up in the output, and under what name.
+#[derive(Debug, Clone, Default)]
+pub struct ColumnSelection {
+    /// keep only these columns, in this order
+    pub select: Option<Vec<String>>,
+    pub exclude: Vec<String>,
+    /// (old, new) pairs
+    pub rename: Vec<(String, String)>,
+}
+
+impl Filter {
+    pub fn matches(&self, row: &Map<String, Value>) -> bool {
+        match self {
+            Filter::Compare(lhs, op, rhs) => {
+                let ordering = compare(lhs.resolve(row), rhs.resolve(row));
+                match op {
+                    CompareOp::Eq => ordering == Some(Ordering::Equal),
+                    CompareOp::Ne => ordering != Some(Ordering::Equal),
+                    CompareOp::Lt => ordering == Some(Ordering::Less),
+                    CompareOp::Le => matches!(ordering, Some(Ordering::Less | Ordering::Equal)),
+                    CompareOp::Gt => ordering == Some(Ordering::Greater),
+                    CompareOp::Ge => {
+                        matches!(ordering, Some(Ordering::Greater | Ordering::Equal))
+                    }
+                }
+            }
+            Filter::Truthy(operand) => match operand.resolve(row) {
+                Value::Null => false,
+                Value::Bool(b) => *b,
+                Value::Number(n) => n.as_f64() != Some(0.0),
+                Value::String(s) => !matches!(s.as_str(), "" | "0" | "false"),
+                _ => true,
+            },
+            Filter::Not(filter) => !filter.matches(row),
+            Filter::And(lhs, rhs) => lhs.matches(row) && rhs.matches(row),
+            Filter::Or(lhs, rhs) => lhs.matches(row) || rhs.matches(row),
+        }
+    }
+
+    /// All columns referenced by the filter.
+    pub fn columns(&self) -> Vec<&str> {
+        let mut columns = Vec::new();
+        self.collect_columns(&mut columns);
+        columns
+    }
+
+    fn collect_columns<'a>(&'a self, columns: &mut Vec<&'a str>) {
+        let mut push = |operand: &'a Operand| {
+            if let Operand::Column(c) = operand {
+                columns.push(c.as_str());
+            }
+        };
+        match self {
+            Filter::Compare(lhs, _, rhs) => {
+                push(lhs);
+                push(rhs);
+            }
+            Filter::Truthy(operand) => push(operand),
+            Filter::Not(filter) => filter.collect_columns(columns),
+            Filter::And(lhs, rhs) | Filter::Or(lhs, rhs) => {
+                lhs.collect_columns(columns);
+                rhs.collect_columns(columns);
+            }
+        }
+    }
+}
+
+impl Operand {
+    fn resolve<'a>(&'a self, row: &'a Map<String, Value>) -> &'a Value {
+        match self {
+            Operand::Column(c) => row.get(c).unwrap_or(&Value::Null),
+            Operand::Literal(v) => v,
+        }
+    }
+}
+
+fn compare(lhs: &Value, rhs: &Value) -> Option<Ordering> {
+    match (lhs, rhs) {
+        (Value::Null, Value::Null) => Some(Ordering::Equal),
+        (Value::Null, _) | (_, Value::Null) => None,
+        _ => match (as_number(lhs), as_number(rhs)) {
+            (Some(l), Some(r)) => l.partial_cmp(&r),
+            _ => Some(cell_to_string(lhs).cmp(&cell_to_string(rhs))),
+        },
+    }
+}
+
+fn as_number(value: &Value) -> Option<f64> {
+    match value {
+        Value::Number(n) => n.as_f64(),
+        Value::String(s) => s.trim().parse().ok(),
+        _ => None,
+    }
+}
+
+impl ColumnSelection {
+    /// Resolve the output columns against the headers: (index into the headers, output name).
+    pub fn resolve<'a>(
+        &self,
+        headers: impl IntoIterator<Item = &'a str>,
+    ) -> Result<Vec<(usize, String)>> {
+        let headers: Vec<&str> = headers.into_iter().collect();
+        let index_of = |name: &str| {
+            headers
+                .iter()
+                .position(|h| *h == name)
+                .ok_or_else(|| anyhow!("Unknown column {:?}", name))
+        };
+        for name in self
+            .exclude
+            .iter()
+            .chain(self.rename.iter().map(|(old, _)| old))
+        {
+            index_of(name)?;
+        }
+        let indexes = match &self.select {
+            Some(select) => select
+                .iter()
+                .map(|name| index_of(name))
+                .collect::<Result<Vec<_>>>()?,
+            None => (0..headers.len()).collect(),
+        };
+        let columns: Vec<(usize, String)> = indexes
+            .into_iter()
+            .filter(|&i| !self.exclude.iter().any(|e| e == headers[i]))
+            .map(|i| {
+                let name = self
+                    .rename
+                    .iter()
+                    .find(|(old, _)| old == headers[i])
+                    .map_or(headers[i], |(_, new)| new.as_str());
+                (i, name.to_string())
+            })
+            .collect();
+        for (pos, (_, name)) in columns.iter().enumerate() {
+            if columns[..pos].iter().any(|(_, prev)| prev == name) {
+                bail!("Output column {:?} appears more than once", name);
+            }
+        }
+        Ok(columns)
+    }
+}
+
+impl FromStr for Filter {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let tokens = tokenize(s)?;
+        let mut parser = Parser { tokens, pos: 0 };
+        let filter = parser.parse_or()?;
+        if let Some(token) = parser.tokens.get(parser.pos) {
+            bail!("Unexpected {} in filter", token);
+        }
+        Ok(filter)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Ident(String),
+    Literal(Value),
+    Op(CompareOp),
+    And,
+    Or,
+    Not,
+    LParen,
+    RParen,
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::Ident(s) => write!(f, "column {:?}", s),
+            Token::Literal(v) => write!(f, "{}", v),
+            Token::Op(op) => write!(f, "{:?}", op),
+            Token::And => write!(f, "'&&'"),
+            Token::Or => write!(f, "'||'"),
+            Token::Not => write!(f, "'!'"),
+            Token::LParen => write!(f, "'('"),
+            Token::RParen => write!(f, "')'"),
+        }
+    }
+}
+
+fn tokenize(s: &str) -> Result<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let mut chars = s.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        let token = match c {
+            c if c.is_whitespace() => {
+                chars.next();
+                continue;
+            }
+            '(' | ')' => {
+                chars.next();
+                if c == '(' {
+                    Token::LParen
+                } else {
+                    Token::RParen
+                }
+            }
+            '"' | '\'' | '`' => {
+                chars.next();
+                let mut text = String::new();
+                loop {
+                    match chars.next() {
+                        Some('\\') => text.extend(chars.next()),
+                        Some(ch) if ch == c => break,
+                        Some(ch) => text.push(ch),
+                        None => bail!("Unterminated quote in filter"),
+                    }
+                }
+                if c == '`' {
+                    Token::Ident(text)
+                } else {
+                    Token::Literal(Value::String(text))
+                }
+            }
+            '=' | '!' | '<' | '>' | '&' | '|' => {
+                chars.next();
+                let eq = chars.next_if_eq(&'=').is_some();
+                match (c, eq) {
+                    ('=', true) => Token::Op(CompareOp::Eq),
+                    ('!', true) => Token::Op(CompareOp::Ne),
+                    ('!', false) => Token::Not,
+                    ('<', true) => Token::Op(CompareOp::Le),
+                    ('<', false) => Token::Op(CompareOp::Lt),
+                    ('>', true) => Token::Op(CompareOp::Ge),
+                    ('>', false) => Token::Op(CompareOp::Gt),
+                    ('&', false) if chars.next_if_eq(&'&').is_some() => Token::And,
+                    ('|', false) if chars.next_if_eq(&'|').is_some() => Token::Or,
+                    _ => bail!("Invalid operator near {:?} in filter", c),
+                }
+            }
+            _ => {
+                let mut word = String::new();
+                while let Some(ch) =
+                    chars.next_if(|ch| ch.is_alphanumeric() || matches!(ch, '_' | '.' | '-'))
+                {
+                    word.push(ch);
+                }
+                if word.is_empty() {
+                    bail!("Unexpected character {:?} in filter", c);
+                }
+                match word.as_str() {
+                    "and" => Token::And,
+                    "or" => Token::Or,
+                    "not" => Token::Not,
+                    "true" => Token::Literal(Value::Bool(true)),
+                    "false" => Token::Literal(Value::Bool(false)),
+                    "null" => Token::Literal(Value::Null),
+                    _ => match word.parse::<f64>() {
+                        Ok(n) if word.starts_with(|ch: char| ch.is_ascii_digit() || ch == '-') => {
+                            Token::Literal(Value::from(n))
+                        }
+                        _ => Token::Ident(word),
+                    },
+                }
+            }
+        };
+        tokens.push(token);
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn eat(&mut self, token: &Token) -> bool {
+        if self.tokens.get(self.pos) == Some(token) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn parse_or(&mut self) -> Result<Filter> {
+        let mut lhs = self.parse_and()?;
+        while self.eat(&Token::Or) {
+            lhs = Filter::Or(Box::new(lhs), Box::new(self.parse_and()?));
+        }
+        Ok(lhs)
+    }
+
+    fn parse_and(&mut self) -> Result<Filter> {
+        let mut lhs = self.parse_not()?;
+        while self.eat(&Token::And) {
+            lhs = Filter::And(Box::new(lhs), Box::new(self.parse_not()?));
+        }
+        Ok(lhs)
+    }
+
+    fn parse_not(&mut self) -> Result<Filter> {
+        if self.eat(&Token::Not) {
+            return Ok(Filter::Not(Box::new(self.parse_not()?)));
+        }
+        if self.eat(&Token::LParen) {
+            let filter = self.parse_or()?;
+            if !self.eat(&Token::RParen) {
+                bail!("Missing ')' in filter");
+            }
+            return Ok(filter);
+        }
+        let lhs = self.parse_operand()?;
+        match self.tokens.get(self.pos) {
+            Some(Token::Op(op)) => {
+                let op = *op;
+                self.pos += 1;
+                Ok(Filter::Compare(lhs, op, self.parse_operand()?))
+            }
+            _ => Ok(Filter::Truthy(lhs)),
+        }
+    }
+
+    fn parse_operand(&mut self) -> Result<Operand> {
+        match self.next() {
+            Some(Token::Ident(name)) => Ok(Operand::Column(name)),
+            Some(Token::Literal(value)) => Ok(Operand::Literal(value)),
+            Some(token) => bail!("Expected a column or value, got {} in filter", token),
+            None => bail!("Unexpected end of filter"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn row(value: Value) -> Map<String, Value> {
+        match value {
+            Value::Object(map) => map,
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn test_parse_filter() -> Result<()> {
+        let filter: Filter =
+            r#"`Kit Number` >= 10 && !(Nationality == "Italy" or active)"#.parse()?;
+        assert_eq!(
+            filter.columns(),
+            vec!["Kit Number", "Nationality", "active"]
+        );
+        assert!("a ==".parse::<Filter>().is_err());
+        assert!("(a == 1".parse::<Filter>().is_err());
+        assert!("a = 1".parse::<Filter>().is_err());
+        assert!("a == 'x".parse::<Filter>().is_err());
+        assert!("a == 1 b".parse::<Filter>().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_filter_matches() -> Result<()> {
+        let player = row(
+            json!({"Name": "Buffon", "Nationality": "Italy", "Kit Number": "77", "Active": false}),
+        );
+        let matches = |s: &str| -> Result<bool> { Ok(s.parse::<Filter>()?.matches(&player)) };
+        assert!(matches(r#"Nationality == "Italy""#)?);
+        assert!(matches("Nationality != 'Poland'")?);
+        // numeric strings compare as numbers
+        assert!(matches("`Kit Number` > 9")?);
+        assert!(matches("`Kit Number` <= 77 and Name < 'C'")?);
+        assert!(!matches("Active")?);
+        assert!(matches("not Active || Missing")?);
+        assert!(!matches("Missing == 1")?);
+        assert!(matches("Missing == null")?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_column_selection() -> Result<()> {
+        let headers = ["Name", "DOB", "Nationality"];
+        let selection = ColumnSelection {
+            exclude: vec!["DOB".into()],
+            rename: vec![("Nationality".into(), "nationality".into())],
+            ..Default::default()
+        };
+        assert_eq!(
+            selection.resolve(headers)?,
+            vec![(0, "Name".to_string()), (2, "nationality".to_string())]
+        );
+
+        let selection = ColumnSelection {
+            select: Some(vec!["Nationality".into(), "Name".into()]),
+            ..Default::default()
+        };
+        assert_eq!(
+            selection.resolve(headers)?,
+            vec![(2, "Nationality".to_string()), (0, "Name".to_string())]
+        );
+
+        let selection = ColumnSelection {
+            exclude: vec!["Email".into()],
+            ..Default::default()
+        };
+        assert!(selection.resolve(headers).is_err());
+
+        let selection = ColumnSelection {
+            rename: vec![("Name".into(), "Nationality".into())],
+            ..Default::default()
+        };
+        assert_eq!(
+            selection.resolve(headers).unwrap_err().to_string(),
+            r#"Output column "Nationality" appears more than once"#
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,215 @@
+use std::{
+    collections::HashSet,
+    io::{BufWriter, Read, Write},
+};
+
+use anyhow::{anyhow, bail, Result};
+use csv::WriterBuilder;
+use serde_json::{Map, Value};
+
+use super::csv_writer::cell_to_string;
+use crate::OutputFormat;
+
+/// Convert an array of objects in json, yaml, toml or ndjson back to csv.
+///
+/// Nested objects are flattened with dotted keys (`address.city`) and arrays with
+/// indexes (`tags[0]`). The header is the union of all keys, in first-seen order.
+pub fn process_csv_from(
+    reader: &mut dyn Read,
+    writer: &mut dyn Write,
+    from: OutputFormat,
+    delimiter: u8,
+) -> Result<()> {
+    // the header is the union of all keys, so the whole input is needed anyway
+    let mut content = String::new();
+    reader.read_to_string(&mut content)?;
+    let rows = parse_rows(&content, from)?;
+
+    let mut headers = Vec::new();
+    let mut seen = HashSet::new();
+    let mut flattened = Vec::with_capacity(rows.len());
+    for (i, row) in rows.into_iter().enumerate() {
+        let Value::Object(row) = row else {
+            bail!("row {} is not an object", i + 1);
+        };
+        let mut flat = Map::new();
+        flatten_into(&mut flat, None, Value::Object(row))
+            .map_err(|e| anyhow!("row {}: {}", i + 1, e))?;
+        for key in flat.keys() {
+            if seen.insert(key.clone()) {
+                headers.push(key.clone());
+            }
+        }
+        flattened.push(flat);
+    }
+
+    let mut writer = WriterBuilder::new()
+        .delimiter(delimiter)
+        .from_writer(BufWriter::new(writer));
+    writer.write_record(&headers)?;
+    for row in &flattened {
+        writer.write_record(
+            headers
+                .iter()
+                .map(|h| row.get(h).map(cell_to_string).unwrap_or_default()),
+        )?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+fn parse_rows(content: &str, from: OutputFormat) -> Result<Vec<Value>> {
+    let doc: Value = match from {
+        OutputFormat::Json => serde_json::from_str(content)?,
+        OutputFormat::Yaml => serde_yaml::from_str(content)?,
+        OutputFormat::Toml => {
+            // rows live under a single array-of-tables key, e.g. `[[rows]]`
+            let doc: Map<String, Value> = toml::from_str(content)?;
+            let mut values = doc.into_iter().map(|(_, v)| v);
+            match (values.next(), values.next()) {
+                (Some(v @ Value::Array(_)), None) => v,
+                _ => bail!("The toml document must contain a single array of tables"),
+            }
+        }
+        OutputFormat::Ndjson => {
+            return content
+                .lines()
+                .enumerate()
+                .filter(|(_, line)| !line.trim().is_empty())
+                .map(|(i, line)| {
+                    serde_json::from_str(line).map_err(|e| anyhow!("line {}: {}", i + 1, e))
+                })
+                .collect();
+        }
+        OutputFormat::Csv => bail!("The input is already csv"),
+    };
+    match doc {
+        Value::Array(rows) => Ok(rows),
+        row @ Value::Object(_) => Ok(vec![row]),
+        _ => bail!("The input must be an array of objects"),
+    }
+}
+
+fn flatten_into(flat: &mut Map<String, Value>, prefix: Option<String>, value: Value) -> Result<()> {
+    let children: Vec<(String, Value)> = match value {
+        Value::Object(map) if !map.is_empty() => map
+            .into_iter()
+            .map(|(k, v)| match &prefix {
+                Some(p) => (format!("{}.{}", p, k), v),
+                None => (k, v),
+            })
+            .collect(),
+        Value::Array(values) if !values.is_empty() => values
+            .into_iter()
+            .enumerate()
+            .map(|(i, v)| {
+                (
+                    format!("{}[{}]", prefix.as_deref().unwrap_or_default(), i),
+                    v,
+                )
+            })
+            .collect(),
+        // an empty record has no columns at all
+        Value::Object(_) if prefix.is_none() => return Ok(()),
+        // scalars, and empty objects/arrays which end up as empty cells
+        value => {
+            let value = match value {
+                Value::Object(_) | Value::Array(_) => Value::Null,
+                v => v,
+            };
+            let key = prefix.unwrap_or_default();
+            if flat.contains_key(&key) {
+                bail!("more than one value flattens to the key {:?}", key);
+            }
+            flat.insert(key, value);
+            return Ok(());
+        }
+    };
+    for (key, child) in children {
+        flatten_into(flat, Some(key), child)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_flatten() -> Result<()> {
+        let mut flat = Map::new();
+        flatten_into(
+            &mut flat,
+            None,
+            json!({"name": "a", "address": {"city": "Turin", "zip": 10121}, "tags": ["x", "y"], "meta": {}}),
+        )?;
+        assert_eq!(
+            Value::Object(flat),
+            json!({"name": "a", "address.city": "Turin", "address.zip": 10121, "tags[0]": "x", "tags[1]": "y", "meta": null})
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_flatten_empty_and_colliding_keys() -> Result<()> {
+        let mut flat = Map::new();
+        flatten_into(&mut flat, None, json!({}))?;
+        assert!(flat.is_empty());
+
+        let err = flatten_into(&mut Map::new(), None, json!({"a.b": 1, "a": {"b": 2}}))
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("\"a.b\""), "{}", err);
+
+        let mut output = Vec::new();
+        process_csv_from(
+            &mut r#"[{}, {"x": 1}]"#.as_bytes(),
+            &mut output,
+            OutputFormat::Json,
+            b',',
+        )?;
+        // csv quotes a lone empty field so the row isn't a blank line
+        assert_eq!(String::from_utf8(output)?, "x\n\"\"\n1\n");
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_rows() -> Result<()> {
+        let expected = vec![json!({"a": 1}), json!({"b": "x"})];
+        assert_eq!(
+            parse_rows(r#"[{"a": 1}, {"b": "x"}]"#, OutputFormat::Json)?,
+            expected
+        );
+        assert_eq!(
+            parse_rows("- a: 1\n- b: x\n", OutputFormat::Yaml)?,
+            expected
+        );
+        assert_eq!(
+            parse_rows("{\"a\": 1}\n\n{\"b\": \"x\"}\n", OutputFormat::Ndjson)?,
+            expected
+        );
+        assert_eq!(
+            parse_rows(
+                "[[rows]]\na = 1\n\n[[rows]]\nb = \"x\"\n",
+                OutputFormat::Toml
+            )?,
+            expected
+        );
+        assert!(parse_rows("1", OutputFormat::Json).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_process_csv_from() -> Result<()> {
+        let mut input =
+            r#"[{"name": "a", "address": {"city": "Turin"}}, {"age": 3, "name": "b"}]"#.as_bytes();
+        let mut output = Vec::new();
+        process_csv_from(&mut input, &mut output, OutputFormat::Json, b';')?;
+        assert_eq!(
+            String::from_utf8(output)?,
+            "name;address.city;age\na;Turin;\nb;;3\n"
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,164 @@
+use anyhow::{anyhow, bail, Result};
+use serde_json::{Map, Value};
+
+/// Largest array index accepted in a header like `tags[9999]`. Every row allocates up to
+/// the highest index, so a huge one would exhaust memory before the first row.
+const MAX_INDEX: usize = 9_999;
+
+/// Builds nested objects and arrays from dotted headers like `address.city` or `tags[0]`.
+#[derive(Debug, Clone)]
+pub struct NestedLayout {
+    paths: Vec<Vec<Segment>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Segment {
+    Key(String),
+    Index(usize),
+}
+
+impl NestedLayout {
+    /// Parse the headers and check that no path is used both as a value and as a container.
+    pub fn try_new<'a>(headers: impl IntoIterator<Item = &'a str>) -> Result<Self> {
+        let headers: Vec<&str> = headers.into_iter().collect();
+        let paths = headers
+            .iter()
+            .map(|h| parse_path(h))
+            .collect::<Result<Vec<_>>>()?;
+        let layout = Self { paths };
+
+        // every row has the same shape, so checking with placeholder values is enough
+        let mut root = Value::Object(Map::new());
+        for (header, path) in headers.iter().zip(&layout.paths) {
+            insert(&mut root, path, Value::Bool(true))
+                .map_err(|e| anyhow!("Invalid nested column {:?}: {}", header, e))?;
+        }
+        Ok(layout)
+    }
+
+    /// Build the nested value from the cells, given in the same order as the headers.
+    pub fn build(&self, cells: impl IntoIterator<Item = Value>) -> Result<Value> {
+        let mut root = Value::Object(Map::new());
+        for (path, cell) in self.paths.iter().zip(cells) {
+            insert(&mut root, path, cell)?;
+        }
+        Ok(root)
+    }
+}
+
+fn parse_path(header: &str) -> Result<Vec<Segment>> {
+    let mut path = Vec::new();
+    for part in header.split('.') {
+        let (key, mut rest) = part.split_at(part.find('[').unwrap_or(part.len()));
+        if key.is_empty() {
+            bail!("Invalid nested column {:?}: empty key", header);
+        }
+        path.push(Segment::Key(key.to_string()));
+        while !rest.is_empty() {
+            let index = rest
+                .strip_prefix('[')
+                .and_then(|r| r.split_once(']'))
+                .and_then(|(index, r)| Some((index.parse::<usize>().ok()?, r)));
+            let Some((index, r)) = index else {
+                bail!("Invalid nested column {:?}: bad array index", header);
+            };
+            if index > MAX_INDEX {
+                bail!(
+                    "Invalid nested column {:?}: array index {} is larger than {}",
+                    header,
+                    index,
+                    MAX_INDEX
+                );
+            }
+            path.push(Segment::Index(index));
+            rest = r;
+        }
+    }
+    Ok(path)
+}
+
+/// Insert a value at the path. Null array slots are gaps that can still be filled.
+fn insert(node: &mut Value, path: &[Segment], value: Value) -> Result<()> {
+    let Some((segment, rest)) = path.split_first() else {
+        if !node.is_null() {
+            bail!("the path is already used as an object or array");
+        }
+        *node = value;
+        return Ok(());
+    };
+    if node.is_null() {
+        *node = match segment {
+            Segment::Key(_) => Value::Object(Map::new()),
+            Segment::Index(_) => Value::Array(Vec::new()),
+        };
+    }
+    let child = match (segment, node) {
+        (Segment::Key(key), Value::Object(map)) => {
+            if rest.is_empty() && map.contains_key(key) {
+                bail!("duplicate column");
+            }
+            map.entry(key.clone()).or_insert(Value::Null)
+        }
+        (Segment::Index(i), Value::Array(values)) => {
+            if values.len() <= *i {
+                let len = i
+                    .checked_add(1)
+                    .ok_or_else(|| anyhow!("array index {} is too large", i))?;
+                values.resize(len, Value::Null);
+            }
+            &mut values[*i]
+        }
+        (Segment::Key(_), _) => bail!("the path is used both as a value and as an object"),
+        (Segment::Index(_), _) => bail!("the path is used both as a value and as an array"),
+    };
+    insert(child, rest, value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_nested_build() -> Result<()> {
+        let layout = NestedLayout::try_new([
+            "name",
+            "address.city",
+            "address.zip",
+            "tags[0]",
+            "tags[2]",
+            "m[0][1].x",
+        ])?;
+        let row = layout.build(
+            ["a", "Turin", "10121", "x", "z", "deep"]
+                .into_iter()
+                .map(Value::from),
+        )?;
+        assert_eq!(
+            row,
+            json!({
+                "name": "a",
+                "address": {"city": "Turin", "zip": "10121"},
+                "tags": ["x", null, "z"],
+                "m": [[null, {"x": "deep"}]]
+            })
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_nested_conflicts() {
+        assert!(NestedLayout::try_new(["a", "a.b"]).is_err());
+        assert!(NestedLayout::try_new(["a.b", "a"]).is_err());
+        assert!(NestedLayout::try_new(["a[0]", "a.b"]).is_err());
+        assert!(NestedLayout::try_new(["a.b", "a.b"]).is_err());
+        assert!(NestedLayout::try_new(["a..b"]).is_err());
+        assert!(NestedLayout::try_new(["x[9999]"]).is_ok());
+        for header in ["x[10000]", "x[4000000000]", "x[18446744073709551615]"] {
+            let err = NestedLayout::try_new([header]).unwrap_err().to_string();
+            assert!(err.contains("is larger than 9999"), "{}", err);
+        }
+        assert!(NestedLayout::try_new(["a[x]"]).is_err());
+        assert!(NestedLayout::try_new(["a[0"]).is_err());
+    }
+}
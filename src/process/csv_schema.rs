@@ -0,0 +1,231 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+    path::Path,
+};
+
+use anyhow::{anyhow, bail, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::{Number, Value};
+
+use crate::load_config;
+
+/// Type of a csv column once it's converted to json/yaml.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ColumnType {
+    String,
+    #[serde(alias = "int")]
+    Integer,
+    #[serde(alias = "number")]
+    Float,
+    #[serde(alias = "bool")]
+    Boolean,
+}
+
+/// Column name to type mapping, loaded from a yaml/json or toml file, e.g.
+///
+/// ```yaml
+/// Name: string
+/// Kit Number: integer
+/// ```
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(transparent)]
+pub struct CsvSchema {
+    columns: HashMap<String, ColumnType>,
+}
+
+impl ColumnType {
+    /// Convert a cell to this type. Empty cells are null unless the column is a string.
+    pub fn convert(&self, cell: &str) -> Result<Value> {
+        if cell.is_empty() {
+            return Ok(match self {
+                ColumnType::String => Value::String(String::new()),
+                _ => Value::Null,
+            });
+        }
+        let value = match self {
+            ColumnType::String => Some(Value::String(cell.to_string())),
+            ColumnType::Integer => parse_integer(cell),
+            ColumnType::Float => parse_float(cell),
+            ColumnType::Boolean => parse_bool(cell),
+        };
+        value.ok_or_else(|| anyhow!("expected {}, got {:?}", self, cell))
+    }
+}
+
+impl fmt::Display for ColumnType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            ColumnType::String => "string",
+            ColumnType::Integer => "integer",
+            ColumnType::Float => "float",
+            ColumnType::Boolean => "boolean",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+impl CsvSchema {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        load_config(path)
+    }
+
+    /// Types for the given headers, in the same order. Every column in the schema must exist.
+    pub fn column_types<'a>(
+        &self,
+        headers: impl IntoIterator<Item = &'a str>,
+    ) -> Result<Vec<Option<ColumnType>>> {
+        let mut missing: HashSet<&str> = self.columns.keys().map(String::as_str).collect();
+        let types = headers
+            .into_iter()
+            .map(|h| {
+                missing.remove(h);
+                self.columns.get(h).copied()
+            })
+            .collect();
+        if !missing.is_empty() {
+            let mut missing: Vec<&str> = missing.into_iter().collect();
+            missing.sort_unstable();
+            bail!("The schema columns {:?} are not in the csv", missing);
+        }
+        Ok(types)
+    }
+}
+
+/// Guess the json value of a cell: integers, floats and booleans become native values,
+/// empty cells become null and everything else stays a string.
+///
+/// Numbers are only inferred when they print back exactly as written, so ids too large
+/// for an integer and spellings like `+5` or `1e3` stay strings instead of losing digits.
+pub fn infer_value(cell: &str) -> Value {
+    if cell.is_empty() {
+        return Value::Null;
+    }
+    parse_integer(cell)
+        .or_else(|| parse_float(cell))
+        .filter(|v| round_trips(v, cell))
+        .or_else(|| parse_bool(cell))
+        .unwrap_or_else(|| Value::String(cell.to_string()))
+}
+
+fn round_trips(value: &Value, cell: &str) -> bool {
+    let printed = value.to_string();
+    printed == cell
+}
+
+// zero-padded values like zip codes or ids are kept as they are
+fn is_zero_padded(cell: &str) -> bool {
+    let digits = cell.trim_start_matches(['-', '+']).as_bytes();
+    digits.len() > 1 && digits[0] == b'0' && digits[1].is_ascii_digit()
+}
+
+fn parse_integer(cell: &str) -> Option<Value> {
+    if is_zero_padded(cell) {
+        return None;
+    }
+    cell.parse::<i64>()
+        .map(Value::from)
+        .or_else(|_| cell.parse::<u64>().map(Value::from))
+        .ok()
+}
+
+fn parse_float(cell: &str) -> Option<Value> {
+    // reject "inf", "NaN" and friends, f64 parses those happily
+    if is_zero_padded(cell)
+        || !cell
+            .bytes()
+            .all(|b| b.is_ascii_digit() || matches!(b, b'-' | b'+' | b'.' | b'e' | b'E'))
+    {
+        return None;
+    }
+    let f = cell.parse::<f64>().ok()?;
+    Number::from_f64(f).map(Value::Number)
+}
+
+fn parse_bool(cell: &str) -> Option<Value> {
+    match cell {
+        "true" | "True" | "TRUE" => Some(Value::Bool(true)),
+        "false" | "False" | "FALSE" => Some(Value::Bool(false)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_infer_value() {
+        assert_eq!(infer_value("42"), json!(42));
+        assert_eq!(infer_value("-7"), json!(-7));
+        assert_eq!(infer_value("3.5"), json!(3.5));
+        assert_eq!(infer_value("1e3"), json!("1e3"));
+        assert_eq!(infer_value("true"), json!(true));
+        assert_eq!(infer_value("FALSE"), json!(false));
+        assert_eq!(infer_value(""), Value::Null);
+        assert_eq!(infer_value("007"), json!("007"));
+        assert_eq!(infer_value("0.5"), json!(0.5));
+        assert_eq!(infer_value("inf"), json!("inf"));
+        assert_eq!(infer_value("Italy"), json!("Italy"));
+    }
+
+    #[test]
+    fn test_infer_large_integers() {
+        assert_eq!(
+            infer_value("9223372036854775808"),
+            json!(9223372036854775808u64)
+        );
+        assert_eq!(infer_value("18446744073709551615"), json!(u64::MAX));
+        assert_eq!(
+            infer_value("123456789012345678901"),
+            json!("123456789012345678901")
+        );
+        assert_eq!(
+            infer_value("-9223372036854775809"),
+            json!("-9223372036854775809")
+        );
+    }
+
+    #[test]
+    fn test_infer_keeps_non_canonical_numbers() {
+        assert_eq!(infer_value("+5"), json!("+5"));
+        assert_eq!(infer_value("1E3"), json!("1E3"));
+        assert_eq!(infer_value("2.50"), json!("2.50"));
+        assert_eq!(infer_value("-0"), json!("-0"));
+        assert_eq!(ColumnType::Integer.convert("+5").unwrap(), json!(5));
+        assert_eq!(
+            ColumnType::Integer.convert("12345678901234567890").unwrap(),
+            json!(12345678901234567890u64)
+        );
+    }
+
+    #[test]
+    fn test_column_type_convert() -> Result<()> {
+        assert_eq!(ColumnType::Integer.convert("1")?, json!(1));
+        assert_eq!(ColumnType::Float.convert("1")?, json!(1.0));
+        assert_eq!(ColumnType::String.convert("1")?, json!("1"));
+        assert_eq!(ColumnType::Boolean.convert("")?, Value::Null);
+        assert_eq!(ColumnType::String.convert("")?, json!(""));
+        assert!(ColumnType::Integer.convert("1.5").is_err());
+        assert!(ColumnType::Boolean.convert("yes").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_schema_column_types() -> Result<()> {
+        let schema: CsvSchema = serde_yaml::from_str("Name: string\nKit Number: int\n")?;
+        let types = schema.column_types(["Name", "Position", "Kit Number"])?;
+        assert_eq!(
+            types,
+            vec![Some(ColumnType::String), None, Some(ColumnType::Integer)]
+        );
+        let err = schema.column_types(["Name", "Name"]).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            r#"The schema columns ["Kit Number"] are not in the csv"#
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,240 @@
+use std::{collections::HashMap, fmt::Write as _, io::Read};
+
+use anyhow::Result;
+use csv::StringRecord;
+use serde::Serialize;
+use serde_json::Value;
+
+use super::csv_schema::{infer_value, ColumnType};
+use crate::CsvReadOptions;
+
+/// Summary of a single csv column.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ColumnStats {
+    pub name: String,
+    /// `None` when every cell is empty
+    #[serde(rename = "type")]
+    pub column_type: Option<ColumnType>,
+    pub count: usize,
+    pub nulls: usize,
+    pub distinct: usize,
+    pub min: Option<f64>,
+    pub max: Option<f64>,
+    pub mean: Option<f64>,
+    /// most common values first
+    pub top: Vec<ValueCount>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ValueCount {
+    pub value: String,
+    pub count: usize,
+}
+
+/// Accumulates a column while the records stream by.
+#[derive(Debug, Default)]
+struct ColumnAccumulator {
+    count: usize,
+    nulls: usize,
+    values: HashMap<String, usize>,
+    integer: bool,
+    float: bool,
+    boolean: bool,
+    sum: f64,
+    min: Option<f64>,
+    max: Option<f64>,
+}
+
+impl ColumnAccumulator {
+    fn new() -> Self {
+        Self {
+            integer: true,
+            float: true,
+            boolean: true,
+            ..Default::default()
+        }
+    }
+
+    fn add(&mut self, cell: &str) {
+        self.count += 1;
+        if cell.is_empty() {
+            self.nulls += 1;
+            return;
+        }
+        *self.values.entry(cell.to_string()).or_default() += 1;
+
+        let value = infer_value(cell);
+        self.integer &= value.is_i64() || value.is_u64();
+        self.float &= value.is_number();
+        self.boolean &= value.is_boolean();
+        if let Value::Number(n) = value {
+            let n = n.as_f64().unwrap_or_default();
+            self.sum += n;
+            self.min = Some(self.min.map_or(n, |m| m.min(n)));
+            self.max = Some(self.max.map_or(n, |m| m.max(n)));
+        }
+    }
+
+    fn finish(self, name: String, top: usize) -> ColumnStats {
+        let non_null = self.count - self.nulls;
+        let column_type = match non_null {
+            0 => None,
+            _ if self.integer => Some(ColumnType::Integer),
+            _ if self.float => Some(ColumnType::Float),
+            _ if self.boolean => Some(ColumnType::Boolean),
+            _ => Some(ColumnType::String),
+        };
+        let numeric = matches!(column_type, Some(ColumnType::Integer | ColumnType::Float));
+
+        let distinct = self.values.len();
+        let mut values: Vec<_> = self.values.into_iter().collect();
+        // most common first, ties by value so the output is stable
+        values.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        let top = values
+            .into_iter()
+            .take(top)
+            .map(|(value, count)| ValueCount { value, count })
+            .collect();
+
+        ColumnStats {
+            name,
+            column_type,
+            count: self.count,
+            nulls: self.nulls,
+            distinct,
+            min: self.min.filter(|_| numeric),
+            max: self.max.filter(|_| numeric),
+            mean: numeric.then(|| self.sum / non_null as f64),
+            top,
+        }
+    }
+}
+
+/// Compute per-column statistics, keeping the `top` most common values for each column.
+pub fn process_csv_stats(
+    reader: &mut dyn Read,
+    opts: &CsvReadOptions,
+    top: usize,
+) -> Result<Vec<ColumnStats>> {
+    let mut reader = opts.reader(reader);
+    let headers = opts.headers(&mut reader)?;
+    let mut columns: Vec<_> = headers.iter().map(|_| ColumnAccumulator::new()).collect();
+
+    let mut record = StringRecord::new();
+    while reader.read_record(&mut record)? {
+        opts.check_record(&headers, &record)?;
+        for (column, cell) in columns.iter_mut().zip(record.iter()) {
+            column.add(cell);
+        }
+    }
+
+    Ok(headers
+        .iter()
+        .zip(columns)
+        .map(|(name, column)| column.finish(name.to_string(), top))
+        .collect())
+}
+
+/// Render the statistics as a plain text table.
+pub fn format_stats_table(stats: &[ColumnStats]) -> String {
+    let header = [
+        "column", "type", "nulls", "distinct", "min", "max", "mean", "top",
+    ]
+    .map(String::from);
+    let number = |n: Option<f64>| n.map(|n| format!("{}", n)).unwrap_or_default();
+    let rows: Vec<[String; 8]> = stats
+        .iter()
+        .map(|s| {
+            let top = s
+                .top
+                .iter()
+                .map(|v| format!("{} ({})", v.value, v.count))
+                .collect::<Vec<_>>()
+                .join(", ");
+            [
+                s.name.clone(),
+                s.column_type.map(|t| t.to_string()).unwrap_or_default(),
+                s.nulls.to_string(),
+                s.distinct.to_string(),
+                number(s.min),
+                number(s.max),
+                s.mean.map(|n| format!("{:.2}", n)).unwrap_or_default(),
+                top,
+            ]
+        })
+        .collect();
+
+    let mut widths = header.clone().map(|h| h.chars().count());
+    for row in &rows {
+        for (w, cell) in widths.iter_mut().zip(row) {
+            *w = (*w).max(cell.chars().count());
+        }
+    }
+
+    let mut table = String::new();
+    for row in std::iter::once(&header).chain(&rows) {
+        let line = row
+            .iter()
+            .zip(widths)
+            .map(|(cell, w)| format!("{:<w$}", cell, w = w))
+            .collect::<Vec<_>>()
+            .join("  ");
+        let _ = writeln!(table, "{}", line.trim_end());
+    }
+    table
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_process_csv_stats() -> Result<()> {
+        let mut input =
+            "name,age,score,active,empty\na,30,1.5,true,\nb,,2,false,\na,20,x,true,\n".as_bytes();
+        let stats = process_csv_stats(&mut input, &CsvReadOptions::default(), 1)?;
+
+        assert_eq!(stats[0].column_type, Some(ColumnType::String));
+        assert_eq!(stats[0].distinct, 2);
+        assert_eq!(
+            stats[0].top,
+            vec![ValueCount {
+                value: "a".to_string(),
+                count: 2
+            }]
+        );
+        assert_eq!(stats[0].mean, None);
+
+        assert_eq!(stats[1].column_type, Some(ColumnType::Integer));
+        assert_eq!(stats[1].nulls, 1);
+        assert_eq!((stats[1].min, stats[1].max), (Some(20.0), Some(30.0)));
+        assert_eq!(stats[1].mean, Some(25.0));
+
+        // a single non-numeric value makes it a string column
+        assert_eq!(stats[2].column_type, Some(ColumnType::String));
+        assert_eq!(stats[2].min, None);
+
+        assert_eq!(stats[3].column_type, Some(ColumnType::Boolean));
+        assert_eq!(stats[4].column_type, None);
+        assert_eq!(stats[4].nulls, 3);
+
+        // integers past i64::MAX are still integers
+        let mut input = "id\n18446744073709551615\n1\n".as_bytes();
+        let stats = process_csv_stats(&mut input, &CsvReadOptions::default(), 1)?;
+        assert_eq!(stats[0].column_type, Some(ColumnType::Integer));
+        Ok(())
+    }
+
+    #[test]
+    fn test_format_stats_table() -> Result<()> {
+        let mut input = "name,age\nalice,30\nbob,20\n".as_bytes();
+        let stats = process_csv_stats(&mut input, &CsvReadOptions::default(), 1)?;
+        let expected = [
+            "column  type     nulls  distinct  min  max  mean   top",
+            "name    string   0      2                          alice (1)",
+            "age     integer  0      2         20   30   25.00  20 (1)",
+        ];
+        assert_eq!(format_stats_table(&stats), expected.join("\n") + "\n");
+        Ok(())
+    }
+}
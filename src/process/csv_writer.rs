@@ -0,0 +1,269 @@
+use std::io::Write;
+
+use anyhow::{bail, Result};
+use csv::{StringRecord, WriterBuilder};
+use serde_json::{Map, Value};
+
+use crate::OutputFormat;
+
+/// Toml has no top level arrays, so rows go under `[[rows]]`.
+const TOML_ROWS_KEY: &str = "rows";
+
+/// Writes converted rows one at a time, so memory doesn't grow with the input.
+pub trait RecordWriter {
+    fn write_record(&mut self, row: &Value) -> Result<()>;
+    fn finish(self: Box<Self>) -> Result<()>;
+}
+
+/// Create the writer for the given format. `headers` and `delimiter` are only used for csv.
+pub fn record_writer<'a, W: Write + 'a>(
+    writer: W,
+    format: OutputFormat,
+    headers: &StringRecord,
+    delimiter: u8,
+) -> Result<Box<dyn RecordWriter + 'a>> {
+    let writer: Box<dyn RecordWriter + 'a> = match format {
+        OutputFormat::Json => Box::new(JsonWriter { writer, count: 0 }),
+        OutputFormat::Yaml => Box::new(YamlWriter { writer, count: 0 }),
+        OutputFormat::Toml => Box::new(TomlWriter { writer, count: 0 }),
+        OutputFormat::Ndjson => Box::new(NdjsonWriter { writer }),
+        OutputFormat::Csv => {
+            let mut writer = WriterBuilder::new()
+                .delimiter(delimiter)
+                .from_writer(writer);
+            writer.write_record(headers)?;
+            Box::new(CsvWriter {
+                writer,
+                headers: headers.clone(),
+            })
+        }
+    };
+    Ok(writer)
+}
+
+/// Same output as `serde_json::to_string_pretty` on the whole array.
+struct JsonWriter<W> {
+    writer: W,
+    count: usize,
+}
+
+/// A single yaml sequence, same output as `serde_yaml::to_string` on the whole array.
+struct YamlWriter<W> {
+    writer: W,
+    count: usize,
+}
+
+/// One `[[rows]]` table per record.
+struct TomlWriter<W> {
+    writer: W,
+    count: usize,
+}
+
+struct NdjsonWriter<W> {
+    writer: W,
+}
+
+struct CsvWriter<W: Write> {
+    writer: csv::Writer<W>,
+    headers: StringRecord,
+}
+
+impl<W: Write> RecordWriter for JsonWriter<W> {
+    fn write_record(&mut self, row: &Value) -> Result<()> {
+        let sep = if self.count == 0 { "[\n  " } else { ",\n  " };
+        let content = serde_json::to_string_pretty(row)?;
+        // newlines inside strings are escaped, so every newline here is structural
+        write!(self.writer, "{}{}", sep, content.replace('\n', "\n  "))?;
+        self.count += 1;
+        Ok(())
+    }
+
+    fn finish(mut self: Box<Self>) -> Result<()> {
+        let end = if self.count == 0 { "[]\n" } else { "\n]\n" };
+        self.writer.write_all(end.as_bytes())?;
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+impl<W: Write> RecordWriter for YamlWriter<W> {
+    fn write_record(&mut self, row: &Value) -> Result<()> {
+        let content = serde_yaml::to_string(row)?;
+        for (i, line) in content.lines().enumerate() {
+            let prefix = match (i, line.is_empty()) {
+                (0, _) => "- ",
+                (_, true) => "",
+                _ => "  ",
+            };
+            writeln!(self.writer, "{}{}", prefix, line)?;
+        }
+        self.count += 1;
+        Ok(())
+    }
+
+    fn finish(mut self: Box<Self>) -> Result<()> {
+        if self.count == 0 {
+            self.writer.write_all(b"[]\n")?;
+        }
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+impl<W: Write> RecordWriter for TomlWriter<W> {
+    fn write_record(&mut self, row: &Value) -> Result<()> {
+        // toml can't represent null, so empty values are left out
+        let mut doc = Map::new();
+        doc.insert(
+            TOML_ROWS_KEY.to_string(),
+            Value::Array(vec![strip_nulls(row.clone(), "")?]),
+        );
+        if self.count > 0 {
+            self.writer.write_all(b"\n")?;
+        }
+        self.writer.write_all(toml::to_string(&doc)?.as_bytes())?;
+        self.count += 1;
+        Ok(())
+    }
+
+    fn finish(mut self: Box<Self>) -> Result<()> {
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+impl<W: Write> RecordWriter for NdjsonWriter<W> {
+    fn write_record(&mut self, row: &Value) -> Result<()> {
+        serde_json::to_writer(&mut self.writer, row)?;
+        self.writer.write_all(b"\n")?;
+        Ok(())
+    }
+
+    fn finish(mut self: Box<Self>) -> Result<()> {
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+impl<W: Write> RecordWriter for CsvWriter<W> {
+    fn write_record(&mut self, row: &Value) -> Result<()> {
+        self.writer
+            .write_record(self.headers.iter().map(|h| cell_to_string(&row[h])))?;
+        Ok(())
+    }
+
+    fn finish(mut self: Box<Self>) -> Result<()> {
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+/// Drop null fields from objects. A null inside an array can't be dropped without shifting
+/// the indexes after it, so it is an error naming the column, e.g. `tags[1]`.
+fn strip_nulls(value: Value, path: &str) -> Result<Value> {
+    match value {
+        Value::Object(map) => map
+            .into_iter()
+            .filter(|(_, v)| !v.is_null())
+            .map(|(k, v)| {
+                let path = if path.is_empty() {
+                    k.clone()
+                } else {
+                    format!("{}.{}", path, k)
+                };
+                Ok((k, strip_nulls(v, &path)?))
+            })
+            .collect::<Result<Map<_, _>>>()
+            .map(Value::Object),
+        Value::Array(values) => values
+            .into_iter()
+            .enumerate()
+            .map(|(i, v)| {
+                let path = format!("{}[{}]", path, i);
+                if v.is_null() {
+                    bail!(
+                        "toml can't represent the empty array value in column {:?}",
+                        path
+                    );
+                }
+                strip_nulls(v, &path)
+            })
+            .collect::<Result<Vec<_>>>()
+            .map(Value::Array),
+        v => Ok(v),
+    }
+}
+
+pub(crate) fn cell_to_string(value: &Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        Value::String(s) => s.clone(),
+        v => v.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn write_all(rows: &[Value], format: OutputFormat, delimiter: u8) -> Result<String> {
+        let headers = StringRecord::from(vec!["name", "age"]);
+        let mut buf = Vec::new();
+        let mut writer = record_writer(&mut buf, format, &headers, delimiter)?;
+        for row in rows {
+            writer.write_record(row)?;
+        }
+        writer.finish()?;
+        Ok(String::from_utf8(buf)?)
+    }
+
+    fn rows() -> Vec<Value> {
+        vec![
+            json!({"name": "alice", "age": 30}),
+            json!({"name": "bob\nsmith", "age": null}),
+        ]
+    }
+
+    #[test]
+    fn test_streaming_matches_buffered_output() -> Result<()> {
+        let rows = rows();
+        assert_eq!(
+            write_all(&rows, OutputFormat::Json, b',')?,
+            serde_json::to_string_pretty(&rows)? + "\n"
+        );
+        assert_eq!(
+            write_all(&rows, OutputFormat::Yaml, b',')?,
+            serde_yaml::to_string(&rows)?
+        );
+        assert_eq!(write_all(&[], OutputFormat::Json, b',')?, "[]\n");
+        assert_eq!(write_all(&[], OutputFormat::Yaml, b',')?, "[]\n");
+        Ok(())
+    }
+
+    #[test]
+    fn test_write_other_formats() -> Result<()> {
+        let rows = rows();
+        assert_eq!(
+            write_all(&rows, OutputFormat::Ndjson, b',')?,
+            "{\"name\":\"alice\",\"age\":30}\n{\"name\":\"bob\\nsmith\",\"age\":null}\n"
+        );
+        assert_eq!(
+            write_all(&rows, OutputFormat::Csv, b'\t')?,
+            "name\tage\nalice\t30\n\"bob\nsmith\"\t\n"
+        );
+        assert_eq!(
+            write_all(&rows, OutputFormat::Toml, b',')?,
+            "[[rows]]\nname = \"alice\"\nage = 30\n\n[[rows]]\nname = \"\"\"\nbob\nsmith\"\"\"\n"
+        );
+
+        let row = json!({"name": "alice", "age": {"tags": ["a", null, "c"]}});
+        assert_eq!(
+            write_all(&[row], OutputFormat::Toml, b',')
+                .unwrap_err()
+                .to_string(),
+            r#"toml can't represent the empty array value in column "age.tags[1]""#
+        );
+        Ok(())
+    }
+}
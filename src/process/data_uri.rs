@@ -0,0 +1,185 @@
+use std::{
+    io::{BufRead, BufReader, Read, Write},
+    path::Path,
+};
+
+use anyhow::{bail, Result};
+use percent_encoding::percent_decode;
+
+use super::b64::{process_decode, process_encode};
+use crate::Base64Format;
+
+const EXTENSIONS: &[(&str, &str)] = &[
+    ("png", "image/png"),
+    ("jpg", "image/jpeg"),
+    ("jpeg", "image/jpeg"),
+    ("gif", "image/gif"),
+    ("webp", "image/webp"),
+    ("avif", "image/avif"),
+    ("svg", "image/svg+xml"),
+    ("ico", "image/x-icon"),
+    ("bmp", "image/bmp"),
+    ("pdf", "application/pdf"),
+    ("json", "application/json"),
+    ("wasm", "application/wasm"),
+    ("js", "text/javascript"),
+    ("mjs", "text/javascript"),
+    ("css", "text/css"),
+    ("html", "text/html"),
+    ("htm", "text/html"),
+    ("txt", "text/plain"),
+    ("csv", "text/csv"),
+    ("xml", "application/xml"),
+    ("yaml", "application/yaml"),
+    ("yml", "application/yaml"),
+    ("toml", "application/toml"),
+    ("woff", "font/woff"),
+    ("woff2", "font/woff2"),
+    ("ttf", "font/ttf"),
+    ("otf", "font/otf"),
+    ("mp3", "audio/mpeg"),
+    ("wav", "audio/wav"),
+    ("ogg", "audio/ogg"),
+    ("mp4", "video/mp4"),
+    ("webm", "video/webm"),
+];
+
+const MAGIC: &[(&[u8], &str)] = &[
+    (b"\x89PNG\r\n\x1a\n", "image/png"),
+    (b"\xff\xd8\xff", "image/jpeg"),
+    (b"GIF87a", "image/gif"),
+    (b"GIF89a", "image/gif"),
+    (b"%PDF-", "application/pdf"),
+    (b"\0asm", "application/wasm"),
+    (b"wOFF", "font/woff"),
+    (b"wOF2", "font/woff2"),
+    (b"OggS", "audio/ogg"),
+    (b"\x1a\x45\xdf\xa3", "video/webm"),
+    (b"\0\0\x01\0", "image/x-icon"),
+];
+
+const DEFAULT_MIME: &str = "application/octet-stream";
+
+/// Guess the MIME type from the file extension, falling back to the leading magic bytes.
+pub fn guess_mime(file_name: &str, head: &[u8]) -> &'static str {
+    let ext = Path::new(file_name)
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| ext.to_ascii_lowercase());
+    if let Some((_, mime)) = EXTENSIONS.iter().find(|(e, _)| Some(*e) == ext.as_deref()) {
+        return mime;
+    }
+    if head.len() >= 12 && &head[..4] == b"RIFF" {
+        match &head[8..12] {
+            b"WEBP" => return "image/webp",
+            b"WAVE" => return "audio/wav",
+            _ => {}
+        }
+    }
+    MAGIC
+        .iter()
+        .find(|(magic, _)| head.starts_with(magic))
+        .map(|(_, mime)| *mime)
+        .unwrap_or(DEFAULT_MIME)
+}
+
+/// Write `data:<mime>;base64,...` for the content of reader. Without an explicit `mime`
+/// it is guessed from `file_name` or the first bytes of the content.
+pub fn process_data_uri_encode(
+    reader: &mut dyn Read,
+    writer: &mut dyn Write,
+    file_name: &str,
+    mime: Option<&str>,
+) -> Result<()> {
+    let mut head = Vec::with_capacity(16);
+    reader.take(16).read_to_end(&mut head)?;
+    let mime = mime.unwrap_or_else(|| guess_mime(file_name, &head));
+
+    write!(writer, "data:{};base64,", mime)?;
+    let mut reader = head.as_slice().chain(reader);
+    process_encode(&mut reader, writer, Base64Format::Standard, None)
+}
+
+/// Parse a data URI, writing its bytes to writer and returning the MIME type
+/// (with any parameters such as `charset`).
+pub fn process_data_uri_decode(reader: &mut dyn Read, writer: &mut dyn Write) -> Result<String> {
+    let mut reader = BufReader::new(reader);
+    let mut header = Vec::new();
+    reader.read_until(b',', &mut header)?;
+    let header = String::from_utf8_lossy(&header);
+    let header = header.trim_start();
+
+    let Some(header) = header
+        .strip_prefix("data:")
+        .and_then(|h| h.strip_suffix(','))
+    else {
+        bail!("Not a data URI, expected `data:[<mime>][;base64],<data>`");
+    };
+    let (mime, base64) = match header.strip_suffix(";base64") {
+        Some(mime) => (mime, true),
+        None => (header, false),
+    };
+    let mime = match mime {
+        "" => "text/plain;charset=US-ASCII".to_string(),
+        m if m.starts_with(';') => format!("text/plain{}", m),
+        m => m.to_string(),
+    };
+
+    if base64 {
+        process_decode(&mut reader, writer, Base64Format::Auto)?;
+    } else {
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data)?;
+        let data: Vec<u8> = percent_decode(data.trim_ascii_end()).collect();
+        writer.write_all(&data)?;
+    }
+    Ok(mime)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_guess_mime() {
+        assert_eq!(guess_mime("logo.PNG", b""), "image/png");
+        assert_eq!(guess_mime("font.woff2", b""), "font/woff2");
+        assert_eq!(guess_mime("-", b"\x89PNG\r\n\x1a\n\0\0"), "image/png");
+        assert_eq!(guess_mime("blob", b"RIFF\0\0\0\0WEBPVP8 "), "image/webp");
+        assert_eq!(guess_mime("blob", b"hello"), DEFAULT_MIME);
+    }
+
+    #[test]
+    fn test_data_uri_round_trip() -> Result<()> {
+        let content = b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR-not-really-an-image";
+        let mut uri = Vec::new();
+        process_data_uri_encode(&mut content.as_slice(), &mut uri, "-", None)?;
+        let uri = String::from_utf8(uri)?;
+        assert!(uri.starts_with("data:image/png;base64,iVBORw0KGgo"));
+
+        let mut data = Vec::new();
+        let mime = process_data_uri_decode(&mut format!("{}\n", uri).as_bytes(), &mut data)?;
+        assert_eq!(mime, "image/png");
+        assert_eq!(data, content);
+        Ok(())
+    }
+
+    #[test]
+    fn test_data_uri_decode_plain() -> Result<()> {
+        let mut data = Vec::new();
+        let mime = process_data_uri_decode(&mut "data:,hello%20world".as_bytes(), &mut data)?;
+        assert_eq!(mime, "text/plain;charset=US-ASCII");
+        assert_eq!(data, b"hello world");
+
+        let mut data = Vec::new();
+        let mime = process_data_uri_decode(
+            &mut "data:text/css;charset=utf-8;base64,Ym9keXt9".as_bytes(),
+            &mut data,
+        )?;
+        assert_eq!(mime, "text/css;charset=utf-8");
+        assert_eq!(data, b"body{}");
+
+        assert!(process_data_uri_decode(&mut "hello".as_bytes(), &mut Vec::new()).is_err());
+        Ok(())
+    }
+}
@@ -1,46 +1,311 @@
-use rand::seq::SliceRandom;
+use anyhow::{bail, Result};
+use rand::{rngs::OsRng, RngCore};
 
+// `I` and `l` are left out, they're too easily mistaken for each other
 pub const UPPER: &[u8] = b"ABCDEFGHJKLMNOPQRSTUVWXYZ";
 pub const LOWER: &[u8] = b"abcdefghijkmnopqrstuvwxyz";
 pub const NUMBER: &[u8] = b"0123456789";
 pub const SYMBOL: &[u8] = b"!@#$%^&*_";
+/// characters easily mistaken for one another, removed by `no_ambiguous`
+pub const AMBIGUOUS: &[u8] = b"Il1|O0";
 
-pub fn process_genpass(
-    length: u8,
-    upper: bool,
-    lower: bool,
-    number: bool,
-    symbol: bool,
-) -> anyhow::Result<String> {
-    let mut rng = rand::thread_rng();
+#[derive(Debug, Clone)]
+pub struct GenPassConfig {
+    pub length: usize,
+    pub upper: bool,
+    pub lower: bool,
+    pub number: bool,
+    pub symbol: bool,
+    /// replaces the default `SYMBOL` set
+    pub symbols: Option<String>,
+    /// characters that must never appear
+    pub exclude: String,
+    pub no_ambiguous: bool,
+    pub min_upper: usize,
+    pub min_lower: usize,
+    pub min_number: usize,
+    pub min_symbol: usize,
+}
+
+impl Default for GenPassConfig {
+    fn default() -> Self {
+        Self {
+            length: 16,
+            upper: true,
+            lower: true,
+            number: true,
+            symbol: true,
+            symbols: None,
+            exclude: String::new(),
+            no_ambiguous: false,
+            min_upper: 1,
+            min_lower: 1,
+            min_number: 1,
+            min_symbol: 1,
+        }
+    }
+}
+
+impl GenPassConfig {
+    /// Start from the defaults with the given length, then chain the setters below.
+    pub fn new(length: usize) -> Self {
+        Self {
+            length,
+            ..Default::default()
+        }
+    }
+
+    pub fn upper(mut self, enabled: bool) -> Self {
+        self.upper = enabled;
+        self
+    }
+
+    pub fn lower(mut self, enabled: bool) -> Self {
+        self.lower = enabled;
+        self
+    }
+
+    pub fn number(mut self, enabled: bool) -> Self {
+        self.number = enabled;
+        self
+    }
+
+    pub fn symbol(mut self, enabled: bool) -> Self {
+        self.symbol = enabled;
+        self
+    }
+
+    pub fn symbols(mut self, symbols: impl Into<String>) -> Self {
+        self.symbols = Some(symbols.into());
+        self
+    }
+
+    pub fn exclude(mut self, exclude: impl Into<String>) -> Self {
+        self.exclude = exclude.into();
+        self
+    }
+
+    pub fn no_ambiguous(mut self, enabled: bool) -> Self {
+        self.no_ambiguous = enabled;
+        self
+    }
+
+    pub fn min_upper(mut self, min: usize) -> Self {
+        self.min_upper = min;
+        self
+    }
+
+    pub fn min_lower(mut self, min: usize) -> Self {
+        self.min_lower = min;
+        self
+    }
+
+    pub fn min_number(mut self, min: usize) -> Self {
+        self.min_number = min;
+        self
+    }
+
+    pub fn min_symbol(mut self, min: usize) -> Self {
+        self.min_symbol = min;
+        self
+    }
+
+    /// The enabled character classes after exclusions, with the minimum count of each.
+    pub fn classes(&self) -> Result<Vec<(&'static str, Vec<u8>, usize)>> {
+        let symbols = match &self.symbols {
+            Some(symbols) => {
+                // no whitespace either, it gets lost when a password is copied or pasted
+                if let Some(c) = symbols.chars().find(|c| !c.is_ascii_punctuation()) {
+                    bail!("{:?} is not an ascii symbol", c);
+                }
+                symbols.as_bytes()
+            }
+            None => SYMBOL,
+        };
+
+        let mut classes = Vec::new();
+        for (name, enabled, table, min) in [
+            ("uppercase", self.upper, UPPER, self.min_upper),
+            ("lowercase", self.lower, LOWER, self.min_lower),
+            ("number", self.number, NUMBER, self.min_number),
+            ("symbol", self.symbol, symbols, self.min_symbol),
+        ] {
+            if !enabled {
+                continue;
+            }
+            let mut chars: Vec<u8> = table
+                .iter()
+                .copied()
+                .filter(|c| !self.exclude.as_bytes().contains(c))
+                .filter(|c| !(self.no_ambiguous && AMBIGUOUS.contains(c)))
+                .collect();
+            chars.sort_unstable();
+            chars.dedup();
+            if chars.is_empty() {
+                bail!("No {} characters left after the exclusions", name);
+            }
+            classes.push((name, chars, min));
+        }
+
+        if classes.is_empty() {
+            bail!("At least one character class must be enabled");
+        }
+        let required: usize = classes.iter().map(|(_, _, min)| min).sum();
+        if required > self.length {
+            bail!(
+                "The minimum counts add up to {} characters, more than the length {}",
+                required,
+                self.length
+            );
+        }
+        Ok(classes)
+    }
+}
+
+/// Generate a password from the operating system's CSPRNG.
+pub fn process_genpass(config: &GenPassConfig) -> Result<String> {
+    process_genpass_with_rng(config, &mut OsRng)
+}
+
+/// Same as `process_genpass` with a caller provided rng, e.g. a seeded one in tests.
+pub fn process_genpass_with_rng(config: &GenPassConfig, rng: &mut dyn RngCore) -> Result<String> {
+    let classes = config.classes()?;
     let mut password = Vec::new();
     let mut chars = Vec::new();
 
-    if upper {
-        chars.extend_from_slice(UPPER);
-        password.push(*UPPER.choose(&mut rng).expect("UPPER won't be empty"));
+    for (_, class, min) in &classes {
+        chars.extend_from_slice(class);
+        for _ in 0..*min {
+            password.push(*pick(rng, class)?);
+        }
+    }
+
+    for _ in password.len()..config.length {
+        password.push(*pick(rng, &chars)?);
+    }
+
+    shuffle(rng, &mut password)?;
+
+    Ok(String::from_utf8(password)?)
+}
+
+/// Uniform index in `0..n`. Draws that fall in the incomplete last block of `u32`
+/// are rejected so that no index is more likely than another (no modulo bias).
+///
+/// `n` must be in `1..=u32::MAX`.
+pub fn uniform_index(rng: &mut dyn RngCore, n: usize) -> Result<usize> {
+    if n == 0 || n > u32::MAX as usize {
+        bail!("Can't pick a random index out of {} items", n);
+    }
+    let n = n as u64;
+    let zone = (1u64 << 32) / n * n;
+    loop {
+        let v = rng.next_u32() as u64;
+        if v < zone {
+            return Ok((v % n) as usize);
+        }
+    }
+}
+
+pub fn pick<'a, T>(rng: &mut dyn RngCore, items: &'a [T]) -> Result<&'a T> {
+    Ok(&items[uniform_index(rng, items.len())?])
+}
+
+/// Fisher-Yates shuffle on top of `uniform_index`.
+pub fn shuffle<T>(rng: &mut dyn RngCore, items: &mut [T]) -> Result<()> {
+    for i in (1..items.len()).rev() {
+        items.swap(i, uniform_index(rng, i + 1)?);
     }
-    if lower {
-        chars.extend_from_slice(LOWER);
-        password.push(*LOWER.choose(&mut rng).expect("LOWER won't be empty"));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+
+    use super::*;
+
+    fn count(password: &str, table: &[u8]) -> usize {
+        password.bytes().filter(|c| table.contains(c)).count()
     }
-    if number {
-        chars.extend_from_slice(NUMBER);
-        password.push(*NUMBER.choose(&mut rng).expect("NUMBER won't be empty"));
+
+    #[test]
+    fn test_genpass_rules() -> Result<()> {
+        let config = GenPassConfig {
+            length: 12,
+            symbols: Some("-+=".to_string()),
+            exclude: "abc".to_string(),
+            no_ambiguous: true,
+            min_number: 3,
+            min_symbol: 2,
+            ..Default::default()
+        };
+        for _ in 0..50 {
+            let password = process_genpass(&config)?;
+            assert_eq!(password.len(), 12);
+            assert!(count(&password, NUMBER) >= 3);
+            assert!(count(&password, b"-+=") >= 2);
+            assert!(count(&password, UPPER) >= 1);
+            assert_eq!(count(&password, b"abc"), 0);
+            assert_eq!(count(&password, AMBIGUOUS), 0);
+            assert_eq!(count(&password, SYMBOL), 0);
+        }
+        Ok(())
     }
-    if symbol {
-        chars.extend_from_slice(SYMBOL);
-        password.push(*SYMBOL.choose(&mut rng).expect("SYMBOL won't be empty"));
+
+    #[test]
+    fn test_genpass_seeded() -> Result<()> {
+        let config = GenPassConfig::new(20).upper(true).min_number(3);
+        let a = process_genpass_with_rng(&config, &mut ChaCha8Rng::seed_from_u64(42))?;
+        let b = process_genpass_with_rng(&config, &mut ChaCha8Rng::seed_from_u64(42))?;
+        assert_eq!(a, b);
+        assert_eq!(a, "SwCDTBN$A^SHvo418Mc2");
+        Ok(())
     }
 
-    for _ in 0..(length - password.len() as u8) {
-        let c = chars
-            .choose(&mut rng)
-            .expect("chars won't be empty in this context");
-        password.push(*c);
+    #[test]
+    fn test_uniform_index() -> Result<()> {
+        let mut rng = ChaCha8Rng::seed_from_u64(7);
+        let mut counts = [0usize; 6];
+        for _ in 0..60_000 {
+            counts[uniform_index(&mut rng, 6)?] += 1;
+        }
+        assert!(counts.iter().all(|c| (9_000..11_000).contains(c)));
+        assert!(uniform_index(&mut rng, 0).is_err());
+        assert!(pick::<u8>(&mut rng, &[]).is_err());
+        Ok(())
     }
 
-    password.shuffle(&mut rng);
+    #[test]
+    fn test_default_tables() -> Result<()> {
+        assert!(!UPPER.contains(&b'I') && !LOWER.contains(&b'l'));
+        for _ in 0..50 {
+            let password = process_genpass(&GenPassConfig::new(64))?;
+            assert!(!password.contains(['I', 'l']), "{}", password);
+        }
+        Ok(())
+    }
 
-    Ok(String::from_utf8(password)?)
+    #[test]
+    fn test_genpass_invalid_config() {
+        let config = GenPassConfig {
+            length: 4,
+            min_number: 4,
+            ..Default::default()
+        };
+        assert!(process_genpass(&config).is_err());
+
+        let config = GenPassConfig {
+            symbol: true,
+            exclude: SYMBOL.iter().map(|c| *c as char).collect(),
+            ..Default::default()
+        };
+        assert!(process_genpass(&config).is_err());
+
+        for symbols in ["ab", "-+ ", "!\t"] {
+            let config = GenPassConfig::new(16).symbols(symbols);
+            assert!(process_genpass(&config).is_err(), "{:?}", symbols);
+        }
+    }
 }
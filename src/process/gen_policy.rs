@@ -0,0 +1,502 @@
+use std::{collections::HashMap, path::Path};
+
+use anyhow::{anyhow, bail, Result};
+use rand::{rngs::OsRng, RngCore};
+use serde::Deserialize;
+
+use super::gen_pass::{process_genpass_with_rng, GenPassConfig, LOWER, NUMBER, SYMBOL, UPPER};
+use crate::load_config;
+
+/// Attempts before giving up on a policy that random passwords rarely satisfy.
+const MAX_ATTEMPTS: usize = 10_000;
+
+/// Password rules of a target system. Every field is optional, e.g.
+///
+/// ```toml
+/// [ldap]
+/// min_length = 12
+/// max_length = 20
+/// number = 2
+/// symbols = "-_."
+/// max_consecutive = 2
+/// max_sequence = 2
+/// ```
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PasswordPolicy {
+    pub min_length: usize,
+    pub max_length: Option<usize>,
+    /// length of generated passwords, by default `min_length` or the sum of the minimum
+    /// counts if that is larger
+    pub length: Option<usize>,
+    /// minimum count of each class
+    pub uppercase: usize,
+    pub lowercase: usize,
+    pub number: usize,
+    pub symbol: usize,
+    /// the allowed symbols, an empty string forbids symbols altogether
+    pub symbols: Option<String>,
+    /// characters that must not appear
+    pub forbidden: String,
+    /// how many times a single character may appear
+    pub max_repeat: Option<usize>,
+    /// longest run of one character, e.g. 2 allows `aa` but not `aaa`
+    pub max_consecutive: Option<usize>,
+    /// longest run of consecutive letters or digits, e.g. 2 allows `ab` but not `abc` or `321`
+    pub max_sequence: Option<usize>,
+}
+
+impl Default for PasswordPolicy {
+    fn default() -> Self {
+        Self {
+            min_length: 12,
+            max_length: None,
+            length: None,
+            uppercase: 0,
+            lowercase: 0,
+            number: 0,
+            symbol: 0,
+            symbols: None,
+            forbidden: String::new(),
+            max_repeat: None,
+            max_consecutive: None,
+            max_sequence: None,
+        }
+    }
+}
+
+/// Named policies, loaded from a toml or yaml/json file with one table per policy.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(transparent)]
+pub struct PolicyFile {
+    policies: HashMap<String, PasswordPolicy>,
+}
+
+impl PolicyFile {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let policies: Self = load_config(path)?;
+        let mut names: Vec<&String> = policies.policies.keys().collect();
+        names.sort_unstable();
+        for name in names {
+            policies.policies[name]
+                .validate()
+                .map_err(|e| anyhow!("Policy {:?}: {}", name, e))?;
+        }
+        Ok(policies)
+    }
+
+    pub fn get(&self, name: &str) -> Result<&PasswordPolicy> {
+        self.policies.get(name).ok_or_else(|| {
+            let mut names: Vec<&str> = self.policies.keys().map(|k| k.as_str()).collect();
+            names.sort_unstable();
+            anyhow!("No policy {:?}, expected one of {:?}", name, names)
+        })
+    }
+}
+
+impl PasswordPolicy {
+    fn symbol_set(&self) -> &[u8] {
+        self.symbols.as_deref().map(str::as_bytes).unwrap_or(SYMBOL)
+    }
+
+    /// Each class with its minimum count and the characters left after `forbidden`.
+    fn classes(&self) -> [(&'static str, Vec<u8>, usize); 4] {
+        [
+            ("uppercase", UPPER, self.uppercase),
+            ("lowercase", LOWER, self.lowercase),
+            ("number", NUMBER, self.number),
+            ("symbol", self.symbol_set(), self.symbol),
+        ]
+        .map(|(name, table, min)| {
+            let mut chars: Vec<u8> = table
+                .iter()
+                .copied()
+                .filter(|c| !self.forbidden.as_bytes().contains(c))
+                .collect();
+            chars.sort_unstable();
+            chars.dedup();
+            (name, chars, min)
+        })
+    }
+
+    /// Length of generated passwords: `length` if set, otherwise `min_length` or the sum of
+    /// the minimum counts, whichever is larger, capped by `max_length`.
+    fn generated_length(&self) -> usize {
+        self.length.unwrap_or_else(|| {
+            let required = self.uppercase + self.lowercase + self.number + self.symbol;
+            let length = self.min_length.max(required);
+            self.max_length.map_or(length, |max| length.min(max))
+        })
+    }
+
+    /// Error if no password can satisfy the policy, naming the rule that can't be met.
+    pub fn validate(&self) -> Result<()> {
+        if let Some(symbols) = &self.symbols {
+            // the same rule as genpass, whitespace gets lost when a password is pasted
+            if let Some(c) = symbols.chars().find(|c| !c.is_ascii_punctuation()) {
+                bail!("symbols contains {:?}, which is not an ascii symbol", c);
+            }
+        }
+        let classes = self.classes();
+        for (name, chars, min) in &classes {
+            if *min > 0 && chars.is_empty() {
+                bail!("needs {} {} characters, but none are allowed", min, name);
+            }
+        }
+        let required = self.uppercase + self.lowercase + self.number + self.symbol;
+        if let Some(max) = self.max_length {
+            if required > max {
+                bail!(
+                    "the minimum counts add up to {} characters, more than max_length {}",
+                    required,
+                    max
+                );
+            }
+            if self.min_length > max {
+                bail!("min_length {} is above max_length {}", self.min_length, max);
+            }
+        }
+        let length = self.generated_length();
+        if let Some(len) = self.length {
+            if len < self.min_length || self.max_length.is_some_and(|max| len > max) {
+                bail!("length {} is outside the min_length/max_length range", len);
+            }
+            if len < required {
+                bail!(
+                    "the minimum counts add up to {} characters, more than length {}",
+                    required,
+                    len
+                );
+            }
+        }
+        if length > 0 {
+            for (name, max) in [
+                ("max_repeat", self.max_repeat),
+                ("max_consecutive", self.max_consecutive),
+                ("max_sequence", self.max_sequence),
+            ] {
+                if max == Some(0) {
+                    bail!("{} = 0 doesn't allow any character", name);
+                }
+            }
+        }
+        if let Some(max) = self.max_repeat {
+            let allowed: usize = classes.iter().map(|(_, chars, _)| chars.len()).sum();
+            if length > max * allowed {
+                bail!(
+                    "length {} needs more than max_repeat {} uses of each of the {} allowed characters",
+                    length,
+                    max,
+                    allowed
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Everything in password that breaks the policy, empty if it complies.
+    pub fn violations(&self, password: &str) -> Vec<String> {
+        let mut violations = Vec::new();
+        let bytes = password.as_bytes();
+        let len = password.chars().count();
+
+        if len < self.min_length {
+            violations.push(format!("shorter than {} characters", self.min_length));
+        }
+        if let Some(max) = self.max_length {
+            if len > max {
+                violations.push(format!("longer than {} characters", max));
+            }
+        }
+
+        // count by ascii class, the generator tables leave out look-alikes such as `I` and `l`
+        let symbols = self.symbol_set();
+        let count = |is_class: fn(&u8) -> bool| bytes.iter().filter(|c| is_class(c)).count();
+        for (name, count, min) in [
+            ("uppercase", count(u8::is_ascii_uppercase), self.uppercase),
+            ("lowercase", count(u8::is_ascii_lowercase), self.lowercase),
+            ("number", count(u8::is_ascii_digit), self.number),
+            (
+                "symbol",
+                bytes.iter().filter(|c| symbols.contains(c)).count(),
+                self.symbol,
+            ),
+        ] {
+            if count < min {
+                violations.push(format!("needs at least {} {} characters", min, name));
+            }
+        }
+
+        let mut outside: Vec<char> = password
+            .chars()
+            .filter(|c| !is_allowed(*c, symbols))
+            .collect();
+        outside.dedup();
+        if !outside.is_empty() {
+            violations.push(format!(
+                "contains characters that aren't allowed: {:?}",
+                outside
+            ));
+        }
+        let forbidden: Vec<char> = self
+            .forbidden
+            .chars()
+            .filter(|c| password.contains(*c))
+            .collect();
+        if !forbidden.is_empty() {
+            violations.push(format!("contains forbidden characters: {:?}", forbidden));
+        }
+
+        if let Some(max) = self.max_repeat {
+            let mut counts: HashMap<char, usize> = HashMap::new();
+            for c in password.chars() {
+                *counts.entry(c).or_default() += 1;
+            }
+            let mut repeated: Vec<char> = counts
+                .into_iter()
+                .filter(|(_, n)| *n > max)
+                .map(|(c, _)| c)
+                .collect();
+            repeated.sort_unstable();
+            if !repeated.is_empty() {
+                violations.push(format!("uses {:?} more than {} times", repeated, max));
+            }
+        }
+        if let Some(max) = self.max_consecutive {
+            if longest_run(bytes, |a, b| a == b) > max {
+                violations.push(format!(
+                    "repeats a character more than {} times in a row",
+                    max
+                ));
+            }
+        }
+        if let Some(max) = self.max_sequence {
+            let ascending = longest_run(bytes, |a, b| same_class(a, b) && a + 1 == b);
+            let descending = longest_run(bytes, |a, b| same_class(a, b) && a == b + 1);
+            if ascending.max(descending) > max {
+                violations.push(format!(
+                    "contains a sequence like `abc` or `321` longer than {}",
+                    max
+                ));
+            }
+        }
+        violations
+    }
+
+    /// The generator settings closest to the policy. Rules it can't express
+    /// (repeats, runs, sequences) are enforced by retrying.
+    fn genpass_config(&self) -> Result<GenPassConfig> {
+        self.validate()?;
+        // a class with no characters left isn't required (validate checked), so turn it off,
+        // which also covers `symbols = ""`
+        let [upper, lower, number, symbol] = self.classes().map(|(_, chars, _)| !chars.is_empty());
+        let mut config = GenPassConfig::new(self.generated_length())
+            .upper(upper)
+            .lower(lower)
+            .number(number)
+            .symbol(symbol)
+            .exclude(self.forbidden.clone())
+            .min_upper(self.uppercase)
+            .min_lower(self.lowercase)
+            .min_number(self.number)
+            .min_symbol(self.symbol);
+        if let Some(symbols) = &self.symbols {
+            config = config.symbols(symbols.clone());
+        }
+        Ok(config)
+    }
+}
+
+fn is_allowed(c: char, symbols: &[u8]) -> bool {
+    c.is_ascii_alphanumeric() || (c.is_ascii() && symbols.contains(&(c as u8)))
+}
+
+fn same_class(a: u8, b: u8) -> bool {
+    (a.is_ascii_digit() && b.is_ascii_digit())
+        || (a.is_ascii_lowercase() && b.is_ascii_lowercase())
+        || (a.is_ascii_uppercase() && b.is_ascii_uppercase())
+}
+
+/// Length of the longest run where every neighbouring pair satisfies `linked`.
+fn longest_run(bytes: &[u8], linked: impl Fn(u8, u8) -> bool) -> usize {
+    let mut longest = bytes.len().min(1);
+    let mut run = 1;
+    for pair in bytes.windows(2) {
+        run = if linked(pair[0], pair[1]) { run + 1 } else { 1 };
+        longest = longest.max(run);
+    }
+    longest
+}
+
+/// Generate a password that satisfies policy.
+pub fn process_genpass_policy(policy: &PasswordPolicy) -> Result<String> {
+    process_genpass_policy_with_rng(policy, &mut OsRng)
+}
+
+pub fn process_genpass_policy_with_rng(
+    policy: &PasswordPolicy,
+    rng: &mut dyn RngCore,
+) -> Result<String> {
+    let config = policy.genpass_config()?;
+    for _ in 0..MAX_ATTEMPTS {
+        let password = process_genpass_with_rng(&config, rng)?;
+        if policy.violations(&password).is_empty() {
+            return Ok(password);
+        }
+    }
+    bail!(
+        "Couldn't generate a password for the policy in {} attempts, it's likely too strict",
+        MAX_ATTEMPTS
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+
+    use super::*;
+
+    fn policy(toml: &str) -> PasswordPolicy {
+        toml::from_str(toml).unwrap()
+    }
+
+    #[test]
+    fn test_length_rule() {
+        let p = policy("min_length = 8\nmax_length = 10");
+        assert!(p.violations("abcdefg")[0].contains("shorter than 8"));
+        assert!(p.violations("Xk9mQ2vL").is_empty());
+        assert!(p.violations("Xk9mQ2vLxxx")[0].contains("longer than 10"));
+    }
+
+    #[test]
+    fn test_class_rule() {
+        let p = policy("min_length = 4\nuppercase = 1\nnumber = 2\nsymbol = 1");
+        assert!(p.violations("Ab12!").is_empty());
+        assert!(p.violations("Il12!").is_empty());
+        let v = p.violations("ab1!");
+        assert_eq!(v.len(), 2);
+        assert!(v[0].contains("uppercase"));
+        assert!(v[1].contains("2 number"));
+    }
+
+    #[test]
+    fn test_allowed_and_forbidden_rule() {
+        let p = policy("min_length = 4\nsymbols = \"-_\"\nforbidden = \"xyz\"");
+        assert!(p.violations("ab-_").is_empty());
+        assert!(p.violations("ab!_")[0].contains("aren't allowed: ['!']"));
+        assert!(p.violations("abx-")[0].contains("forbidden characters: ['x']"));
+        let p = policy("min_length = 4\nsymbols = \"\"");
+        assert!(!p.violations("ab-c").is_empty());
+    }
+
+    #[test]
+    fn test_impossible_policy() -> Result<()> {
+        let err = policy("symbol = 1\nsymbols = \"\"").validate().unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "needs 1 symbol characters, but none are allowed"
+        );
+        let err = policy("number = 2\nforbidden = \"0123456789\"")
+            .validate()
+            .unwrap_err();
+        assert!(err.to_string().contains("2 number"), "{}", err);
+        let err = policy("min_length = 4\nmax_length = 6\nnumber = 4\nsymbol = 3")
+            .validate()
+            .unwrap_err();
+        assert!(err.to_string().contains("add up to 7"), "{}", err);
+        policy("symbol = 0\nsymbols = \"\"").validate()?;
+        let err = policy("max_sequence = 0").validate().unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "max_sequence = 0 doesn't allow any character"
+        );
+        // 25 + 25 + 10 allowed characters, each used at most once
+        let err = policy("length = 61\nmax_repeat = 1\nsymbols = \"\"")
+            .validate()
+            .unwrap_err();
+        assert!(err.to_string().contains("60 allowed"), "{}", err);
+        policy("length = 60\nmax_repeat = 1\nsymbols = \"\"").validate()?;
+        let err = policy("symbols = \"- _\"").validate().unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "symbols contains ' ', which is not an ascii symbol"
+        );
+
+        let path = std::env::temp_dir().join(format!("rcli_policy_{}.toml", std::process::id()));
+        fs::write(
+            &path,
+            "[ok]\nmin_length = 8\n\n[broken]\nsymbol = 1\nsymbols = \"\"\n",
+        )?;
+        let err = PolicyFile::load(&path).unwrap_err().to_string();
+        fs::remove_file(&path)?;
+        assert!(err.starts_with("Policy \"broken\": "), "{}", err);
+        Ok(())
+    }
+
+    #[test]
+    fn test_repeat_rule() {
+        let p = policy("min_length = 4\nmax_repeat = 1");
+        assert!(p.violations("abcd").is_empty());
+        assert!(p.violations("abca")[0].contains("['a'] more than 1"));
+    }
+
+    #[test]
+    fn test_consecutive_rule() {
+        let p = policy("min_length = 4\nmax_consecutive = 2");
+        assert!(p.violations("aabb").is_empty());
+        assert!(!p.violations("baaa").is_empty());
+    }
+
+    #[test]
+    fn test_sequence_rule() {
+        let p = policy("min_length = 4\nmax_sequence = 2");
+        assert!(p.violations("ab1x9Z").is_empty());
+        assert!(!p.violations("xabc").is_empty());
+        assert!(!p.violations("x321").is_empty());
+        assert!(!p.violations("XYZw").is_empty());
+        // a digit followed by a letter isn't a sequence
+        assert!(p.violations("x89ab").is_empty());
+    }
+
+    #[test]
+    fn test_generate_satisfies_policy() -> Result<()> {
+        let policies: PolicyFile = serde_yaml::from_str(
+            "strict:\n  min_length: 16\n  uppercase: 2\n  number: 3\n  symbol: 2\n  symbols: '-_'\n  forbidden: 'O0Il1'\n  max_repeat: 2\n  max_consecutive: 1\n  max_sequence: 2\n",
+        )?;
+        let p = policies.get("strict")?;
+        let mut rng = ChaCha8Rng::seed_from_u64(1);
+        for _ in 0..20 {
+            let password = process_genpass_policy_with_rng(p, &mut rng)?;
+            assert_eq!(password.len(), 16);
+            assert!(p.violations(&password).is_empty());
+        }
+        assert!(policies.get("missing").is_err());
+
+        let impossible = policy("min_length = 12\nmax_repeat = 0");
+        assert!(process_genpass_policy(&impossible).is_err());
+        let bad_length = policy("min_length = 12\nmax_length = 16\nlength = 20");
+        assert!(process_genpass_policy(&bad_length).is_err());
+
+        // without `length`, long enough for the minimum counts
+        let p = policy("min_length = 8\nuppercase = 3\nlowercase = 3\nnumber = 3\nsymbol = 3");
+        let password = process_genpass_policy_with_rng(&p, &mut rng)?;
+        assert_eq!(password.len(), 12);
+        assert!(p.violations(&password).is_empty());
+        let p = policy("min_length = 8\nmax_length = 10\nnumber = 3\nsymbol = 3");
+        assert_eq!(process_genpass_policy_with_rng(&p, &mut rng)?.len(), 8);
+
+        // a class that isn't required and has nothing left is left out
+        let p = policy("min_length = 10\nforbidden = \"0123456789\"");
+        let password = process_genpass_policy_with_rng(&p, &mut rng)?;
+        assert!(
+            !password.bytes().any(|c| c.is_ascii_digit()),
+            "{}",
+            password
+        );
+        assert!(p.violations(&password).is_empty());
+        Ok(())
+    }
+}
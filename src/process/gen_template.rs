@@ -0,0 +1,299 @@
+use std::str::FromStr;
+
+use anyhow::{anyhow, bail, Result};
+use rand::{rngs::OsRng, RngCore};
+
+use super::gen_pass::{pick, LOWER, NUMBER, SYMBOL, UPPER};
+
+const VOWELS: &[u8] = b"aeiou";
+const CONSONANTS: &[u8] = b"bcdfghjklmnprstvz";
+const HEX: &[u8] = b"0123456789abcdef";
+/// characters of the base62 crc suffix, in value order
+const BASE62: &[u8] = b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz";
+/// 62^6 > 2^32, so six characters hold any crc32
+const CRC_LEN: usize = 6;
+/// Largest count accepted in a placeholder like `{hex:9999}`, the token is built in memory.
+const MAX_COUNT: usize = 9_999;
+
+/// A token template such as `rk_live_{base62:32}{crc}` or `{[A-Z2-9]:4}-{[A-Z2-9]:4}`.
+///
+/// - `{class:N}` draws N characters of a class: `upper`, `lower`, `number`, `symbol`,
+///   `alpha`, `alnum`/`base62` or `hex`. N defaults to 1.
+/// - `{[...]:N}` draws from the listed characters, `a-z` style ranges are allowed. The set
+///   may hold `:` and `}`, and a `]` right after the opening `[` is part of it: `{[]:}]:4}`.
+/// - `{syllable:N}` is N pronounceable consonant-vowel pairs.
+/// - `{crc}` is the crc32 of everything before it, as 6 base62 characters.
+/// - `{{` and `}}` are literal braces, everything else is copied as is.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Template {
+    parts: Vec<Part>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Part {
+    Literal(String),
+    Chars(Vec<u8>, usize),
+    Syllables(usize),
+    Crc,
+}
+
+impl FromStr for Template {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut parts = Vec::new();
+        let mut literal = String::new();
+        let mut chars = s.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.as_str().starts_with('{') => {
+                    chars.next();
+                    literal.push('{');
+                }
+                '}' if chars.as_str().starts_with('}') => {
+                    chars.next();
+                    literal.push('}');
+                }
+                '{' => {
+                    let rest = chars.as_str();
+                    // a set may contain `}`, so the placeholder only ends after the set
+                    let from = if rest.starts_with('[') {
+                        set_end(rest).ok_or_else(|| anyhow!("Unclosed `[` in template {:?}", s))?
+                    } else {
+                        0
+                    };
+                    let end = rest[from..]
+                        .find('}')
+                        .map(|i| i + from)
+                        .ok_or_else(|| anyhow!("Unclosed `{{` in template {:?}", s))?;
+                    if !literal.is_empty() {
+                        parts.push(Part::Literal(std::mem::take(&mut literal)));
+                    }
+                    parts.push(parse_placeholder(&rest[..end])?);
+                    chars = rest[end + 1..].chars();
+                }
+                '}' => bail!(
+                    "Unmatched `}}` in template {:?}, use `}}}}` for a literal one",
+                    s
+                ),
+                c => literal.push(c),
+            }
+        }
+        if !literal.is_empty() {
+            parts.push(Part::Literal(literal));
+        }
+        Ok(Self { parts })
+    }
+}
+
+/// Index of the `]` closing the set at the start of `s`. A `]` right after the `[` is a
+/// member of the set, so the search starts one character later.
+fn set_end(s: &str) -> Option<usize> {
+    s.get(2..)?.find(']').map(|i| i + 2)
+}
+
+fn parse_placeholder(s: &str) -> Result<Part> {
+    // the count always follows the set or the class name, so `:` inside a set is fine
+    let (name, count) = if s.starts_with('[') {
+        let end = set_end(s).ok_or_else(|| anyhow!("Unclosed `[` in `{{{}}}`", s))?;
+        (&s[..=end], &s[end + 1..])
+    } else {
+        s.split_at(s.find(':').unwrap_or(s.len()))
+    };
+    let count = match count {
+        "" => 1,
+        count => count
+            .strip_prefix(':')
+            .and_then(|n| n.parse::<usize>().ok())
+            .ok_or_else(|| anyhow!("Invalid count in `{{{}}}`", s))?,
+    };
+    if count == 0 {
+        bail!("The count in `{{{}}}` must be at least 1", s);
+    }
+    if count > MAX_COUNT {
+        bail!("The count in `{{{}}}` is larger than {}", s, MAX_COUNT);
+    }
+
+    if let Some(set) = name.strip_prefix('[').and_then(|n| n.strip_suffix(']')) {
+        return Ok(Part::Chars(parse_set(set)?, count));
+    }
+    let table: Vec<u8> = match name {
+        "upper" => UPPER.to_vec(),
+        "lower" => LOWER.to_vec(),
+        "number" | "digit" => NUMBER.to_vec(),
+        "symbol" => SYMBOL.to_vec(),
+        "alpha" => [UPPER, LOWER].concat(),
+        "alnum" | "base62" => BASE62.to_vec(),
+        "hex" => HEX.to_vec(),
+        "syllable" => return Ok(Part::Syllables(count)),
+        "crc" if s == "crc" => return Ok(Part::Crc),
+        _ => bail!("Unknown placeholder `{{{}}}`", s),
+    };
+    Ok(Part::Chars(table, count))
+}
+
+/// Expand `A-Z` style ranges in a custom character set.
+fn parse_set(set: &str) -> Result<Vec<u8>> {
+    if !set.is_ascii() {
+        bail!("Character set [{}] must be ascii", set);
+    }
+    let bytes = set.as_bytes();
+    let mut chars = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        if i + 2 < bytes.len() && bytes[i + 1] == b'-' {
+            let (start, end) = (bytes[i], bytes[i + 2]);
+            if start > end {
+                bail!("Invalid range {}-{}", start as char, end as char);
+            }
+            chars.extend(start..=end);
+            i += 3;
+        } else {
+            chars.push(bytes[i]);
+            i += 1;
+        }
+    }
+    chars.sort_unstable();
+    chars.dedup();
+    if chars.is_empty() {
+        bail!("Character set [] is empty");
+    }
+    Ok(chars)
+}
+
+fn crc_suffix(data: &[u8]) -> String {
+    let mut crc = crc32fast::hash(data) as usize;
+    let mut suffix = vec![b'0'; CRC_LEN];
+    for c in suffix.iter_mut().rev() {
+        *c = BASE62[crc % BASE62.len()];
+        crc /= BASE62.len();
+    }
+    String::from_utf8(suffix).expect("base62 is ascii")
+}
+
+impl Template {
+    pub fn generate(&self, rng: &mut dyn RngCore) -> String {
+        let mut out = String::new();
+        for part in &self.parts {
+            match part {
+                Part::Literal(s) => out.push_str(s),
+                Part::Chars(table, count) => {
+                    let table = table.as_slice();
+                    out.extend((0..*count).map(|_| {
+                        *pick(rng, table).expect("sets are checked to be non-empty") as char
+                    }));
+                }
+                Part::Syllables(count) => {
+                    for _ in 0..*count {
+                        out.push(*pick(rng, CONSONANTS).expect("CONSONANTS won't be empty") as char);
+                        out.push(*pick(rng, VOWELS).expect("VOWELS won't be empty") as char);
+                    }
+                }
+                Part::Crc => {
+                    let suffix = crc_suffix(out.as_bytes());
+                    out.push_str(&suffix);
+                }
+            }
+        }
+        out
+    }
+
+    /// Whether the `{crc}` suffix at the end of token matches the rest of it.
+    pub fn verify_crc(token: &str) -> bool {
+        token.len() > CRC_LEN && token.is_char_boundary(token.len() - CRC_LEN) && {
+            let (body, suffix) = token.split_at(token.len() - CRC_LEN);
+            crc_suffix(body.as_bytes()) == suffix
+        }
+    }
+}
+
+/// Templates are checked when parsed, so generating can't fail.
+pub fn process_gentemplate(template: &Template) -> String {
+    process_gentemplate_with_rng(template, &mut OsRng)
+}
+
+pub fn process_gentemplate_with_rng(template: &Template, rng: &mut dyn RngCore) -> String {
+    template.generate(rng)
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+
+    use super::*;
+
+    #[test]
+    fn test_template_parse() -> Result<()> {
+        let template: Template = "{{x}}-{[A-C0-1]:2}{crc}".parse()?;
+        assert_eq!(
+            template.parts,
+            vec![
+                Part::Literal("{x}-".to_string()),
+                Part::Chars(b"01ABC".to_vec(), 2),
+                Part::Crc,
+            ]
+        );
+        // `:`, `}` and a leading `]` are members of a set
+        let template: Template = "{[]:}]:3}{[a:b]}".parse()?;
+        assert_eq!(
+            template.parts,
+            vec![
+                Part::Chars(b":]}".to_vec(), 3),
+                Part::Chars(b":ab".to_vec(), 1),
+            ]
+        );
+        let template: Template = "{syllable:9999}".parse()?;
+        assert_eq!(template.parts, vec![Part::Syllables(9999)]);
+        for bad in [
+            "{upper",
+            "x}",
+            "{upper:0}",
+            "{upper:10000}",
+            "{upper:18446744073709551615}",
+            "{upper:x}",
+            "{upper:2:3}",
+            "{[ab]3}",
+            "{[ab}",
+            "{nope}",
+            "{[z-a]}",
+        ] {
+            assert!(bad.parse::<Template>().is_err(), "{}", bad);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_template_generate() -> Result<()> {
+        let mut rng = ChaCha8Rng::seed_from_u64(3);
+        let key: Template = "{[A-HJ-NP-Z2-9]:4}-{[A-HJ-NP-Z2-9]:4}-{[A-HJ-NP-Z2-9]:4}".parse()?;
+        let key = process_gentemplate_with_rng(&key, &mut rng);
+        assert_eq!(key.len(), 14);
+        assert!(key.split('-').all(|g| g.len() == 4
+            && g.bytes()
+                .all(|c| c.is_ascii_digit() || c.is_ascii_uppercase())));
+        assert!(!key.contains(['I', 'O', '0', '1']));
+
+        let word: Template = "{syllable:4}".parse()?;
+        let word = process_gentemplate_with_rng(&word, &mut rng);
+        assert!(word
+            .as_bytes()
+            .chunks(2)
+            .all(|s| CONSONANTS.contains(&s[0]) && VOWELS.contains(&s[1])));
+
+        let api_key: Template = "rk_live_{base62:32}{crc}".parse()?;
+        let api_key = process_gentemplate_with_rng(&api_key, &mut rng);
+        assert_eq!(api_key.len(), 8 + 32 + 6);
+        assert!(Template::verify_crc(&api_key));
+        let mut tampered = api_key.into_bytes();
+        tampered[10] = if tampered[10] == b'a' { b'b' } else { b'a' };
+        assert!(!Template::verify_crc(&String::from_utf8(tampered)?));
+        Ok(())
+    }
+
+    #[test]
+    fn test_crc_suffix() {
+        // crc32("123456789") is 0xcbf43926
+        assert_eq!(crc_suffix(b"123456789"), "3jZRME");
+    }
+}
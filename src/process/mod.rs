@@ -1,15 +1,51 @@
 mod b64;
+mod b64_doc;
+mod codec;
 mod csv_convert;
+mod csv_filter;
+mod csv_from;
+mod csv_nested;
+mod csv_schema;
+mod csv_stats;
+mod csv_writer;
+mod data_uri;
 mod gen_pass;
+mod gen_policy;
+mod gen_template;
 mod http_serve;
 mod jwt_process;
+mod passphrase;
+mod strength;
 mod text;
 
 pub use b64::{process_decode, process_encode};
-pub use csv_convert::process_csv;
-pub use gen_pass::process_genpass;
+pub use b64_doc::process_b64_doc;
+pub use codec::{
+    process_codec_decode, process_codec_encode, Base32Codec, Base58Codec, Base64Codec, Codec,
+    HexCodec, UrlCodec,
+};
+pub use csv_convert::{process_csv, CsvConvertOptions, CsvReadOptions, CsvReport};
+pub use csv_filter::{ColumnSelection, CompareOp, Filter, Operand};
+pub use csv_from::process_csv_from;
+pub use csv_nested::NestedLayout;
+pub use csv_schema::{infer_value, ColumnType, CsvSchema};
+pub use csv_stats::{format_stats_table, process_csv_stats, ColumnStats, ValueCount};
+pub use csv_writer::{record_writer, RecordWriter};
+pub use data_uri::{guess_mime, process_data_uri_decode, process_data_uri_encode};
+pub use gen_pass::{process_genpass, process_genpass_with_rng, GenPassConfig};
+pub use gen_policy::{
+    process_genpass_policy, process_genpass_policy_with_rng, PasswordPolicy, PolicyFile,
+};
+pub use gen_template::{process_gentemplate, process_gentemplate_with_rng, Template};
 pub use http_serve::process_http_serve;
 pub use jwt_process::{sign as jwt_sign, verify as jwt_verify};
+pub use passphrase::{
+    parse_wordlist, process_passphrase, process_passphrase_with_rng, PassphraseConfig, WORDLIST,
+};
+pub use strength::{
+    password_check, password_report, PasswordCheck, PasswordReport, CHECK_HEADERS, REPORT_HEADERS,
+};
 pub use text::{
-    process_text_key_generate, process_text_sign, process_text_verify, ChaCha20Poly1305DD,
+    process_text_key_generate, process_text_sign, process_text_sign_file, process_text_verify,
+    process_text_verify_file, ChaCha20Poly1305DD,
 };
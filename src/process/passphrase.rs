@@ -0,0 +1,167 @@
+use std::collections::HashSet;
+
+use anyhow::{bail, Result};
+use rand::{rngs::OsRng, RngCore};
+
+use super::gen_pass::{pick, uniform_index};
+
+/// 7776 (6^5) common english words, one per line, so 5 dice pick a word. Built from word
+/// frequency lists with names, places, slang, violent, sexual and offensive words taken out, it
+/// isn't the EFF list.
+pub const WORDLIST: &str = include_str!("../../assets/wordlist.txt");
+
+#[derive(Debug, Clone)]
+pub struct PassphraseConfig {
+    pub words: usize,
+    pub separator: String,
+    pub capitalize: bool,
+    /// append a random digit to the passphrase
+    pub digit: bool,
+}
+
+impl Default for PassphraseConfig {
+    fn default() -> Self {
+        Self {
+            words: 6,
+            separator: "-".to_string(),
+            capitalize: false,
+            digit: false,
+        }
+    }
+}
+
+impl PassphraseConfig {
+    /// Entropy in bits of a passphrase drawn from a list of `list_len` words.
+    pub fn entropy(&self, list_len: usize) -> f64 {
+        let mut bits = self.words as f64 * (list_len as f64).log2();
+        if self.digit {
+            bits += 10f64.log2();
+        }
+        bits
+    }
+}
+
+/// Parse a wordlist with one word per line. EFF style lines with leading dice numbers
+/// (`11111\tabacus`) are accepted, blank lines and duplicates are dropped.
+pub fn parse_wordlist(content: &str) -> Result<Vec<&str>> {
+    let mut seen = HashSet::new();
+    let words: Vec<&str> = content
+        .lines()
+        .filter_map(|line| line.split_whitespace().last())
+        .filter(|word| seen.insert(*word))
+        .collect();
+    if words.len() < 2 {
+        bail!("The wordlist needs at least 2 distinct words");
+    }
+    Ok(words)
+}
+
+pub fn process_passphrase(config: &PassphraseConfig, wordlist: &[&str]) -> Result<String> {
+    process_passphrase_with_rng(config, wordlist, &mut OsRng)
+}
+
+pub fn process_passphrase_with_rng(
+    config: &PassphraseConfig,
+    wordlist: &[&str],
+    rng: &mut dyn RngCore,
+) -> Result<String> {
+    if config.words == 0 {
+        bail!("The passphrase needs at least 1 word");
+    }
+    if wordlist.is_empty() {
+        bail!("The wordlist is empty");
+    }
+    let words = (0..config.words)
+        .map(|_| {
+            let word = pick(rng, wordlist)?;
+            Ok(if config.capitalize {
+                capitalize(word)
+            } else {
+                word.to_string()
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let mut passphrase = words.join(&config.separator);
+    if config.digit {
+        passphrase.push(char::from(b'0' + uniform_index(rng, 10)? as u8));
+    }
+    Ok(passphrase)
+}
+
+fn capitalize(word: &str) -> String {
+    let mut chars = word.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+
+    use super::*;
+
+    #[test]
+    fn test_bundled_wordlist() -> Result<()> {
+        // parse_wordlist drops duplicates, so compare against the raw lines
+        let lines: Vec<&str> = WORDLIST.lines().collect();
+        let words = parse_wordlist(WORDLIST)?;
+        assert_eq!(words.len(), lines.len(), "the wordlist has duplicates");
+        assert_eq!(words.len(), 6usize.pow(5));
+        assert!(words
+            .iter()
+            .all(|w| w.len() >= 4 && w.bytes().all(|b| b.is_ascii_lowercase())));
+        // spot checks that violent, sexual, slang and place words stay out
+        let denied = [
+            "asia", "bombing", "corpse", "corpses", "drugged", "freaking", "kidnap", "kill",
+            "killed", "lingerie", "massacre", "murder", "murdered", "prison", "rape", "seduced",
+            "stabbing", "thug", "torture", "tortured", "victim", "violent", "weapon",
+        ];
+        let found: Vec<&str> = denied
+            .into_iter()
+            .filter(|w| words.iter().any(|word| word == w))
+            .collect();
+        assert!(
+            found.is_empty(),
+            "denied words in the wordlist: {:?}",
+            found
+        );
+
+        // 6 words of log2(7776) = 12.92 bits each
+        let config = PassphraseConfig::default();
+        assert!((config.entropy(words.len()) - 77.55).abs() < 0.01);
+        Ok(())
+    }
+
+    #[test]
+    fn test_passphrase() -> Result<()> {
+        let words = parse_wordlist("11111\tapple\n11112\tbanana\n\n11113\tcherry\n")?;
+        assert_eq!(words, ["apple", "banana", "cherry"]);
+
+        let config = PassphraseConfig {
+            words: 4,
+            separator: ".".to_string(),
+            capitalize: true,
+            digit: true,
+        };
+        let passphrase = process_passphrase(&config, &words)?;
+        let (phrase, digit) = passphrase.split_at(passphrase.len() - 1);
+        assert!(digit.chars().all(|c| c.is_ascii_digit()));
+        let parts: Vec<&str> = phrase.split('.').collect();
+        assert_eq!(parts.len(), 4);
+        assert!(parts
+            .iter()
+            .all(|p| ["Apple", "Banana", "Cherry"].contains(p)));
+
+        let words = parse_wordlist(WORDLIST)?;
+        let passphrase =
+            process_passphrase_with_rng(&config, &words, &mut ChaCha8Rng::seed_from_u64(42))?;
+        assert_eq!(passphrase, "Vigorous.Approval.Infant.Pleaded2");
+
+        assert!(parse_wordlist("only\nonly\n").is_err());
+        Ok(())
+    }
+}
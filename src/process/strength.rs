@@ -0,0 +1,102 @@
+use anyhow::Result;
+use serde::Serialize;
+use zxcvbn::zxcvbn;
+
+/// zxcvbn estimate of a password, one row of the batch output.
+#[derive(Debug, Clone, Serialize)]
+pub struct PasswordReport {
+    pub password: String,
+    pub score: u8,
+    pub guesses: u64,
+    /// time to crack offline against a slow hash (10k guesses/s), e.g. `3 centuries`
+    pub crack_time: String,
+}
+
+/// Column names of `PasswordReport`, used as the csv header.
+pub const REPORT_HEADERS: &[&str] = &["password", "score", "guesses", "crack_time"];
+
+/// A `PasswordReport` with zxcvbn's feedback, the output of `rcli genpass check`.
+#[derive(Debug, Clone, Serialize)]
+pub struct PasswordCheck {
+    #[serde(flatten)]
+    pub report: PasswordReport,
+    pub warning: Option<String>,
+    pub suggestions: Vec<String>,
+    /// broken rules when checked against a `PasswordPolicy`
+    pub violations: Vec<String>,
+}
+
+pub const CHECK_HEADERS: &[&str] = &[
+    "password",
+    "score",
+    "guesses",
+    "crack_time",
+    "warning",
+    "suggestions",
+    "violations",
+];
+
+pub fn password_report(password: &str) -> Result<PasswordReport> {
+    Ok(password_check(password, &[])?.report)
+}
+
+/// Estimate the strength of password. `user_inputs` such as the user name or the company
+/// name are added to zxcvbn's dictionaries, so passwords built from them score lower.
+pub fn password_check(password: &str, user_inputs: &[&str]) -> Result<PasswordCheck> {
+    let estimate = zxcvbn(password, user_inputs)?;
+    let (warning, suggestions) = match estimate.feedback() {
+        Some(feedback) => (
+            feedback.warning().map(|w| w.to_string()),
+            feedback
+                .suggestions()
+                .iter()
+                .map(|s| s.to_string())
+                .collect(),
+        ),
+        None => (None, Vec::new()),
+    };
+    Ok(PasswordCheck {
+        report: PasswordReport {
+            password: password.to_string(),
+            score: estimate.score(),
+            guesses: estimate.guesses(),
+            crack_time: estimate
+                .crack_times()
+                .offline_slow_hashing_1e4_per_second()
+                .to_string(),
+        },
+        warning,
+        suggestions,
+        violations: Vec::new(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_password_report() -> Result<()> {
+        let weak = password_report("password")?;
+        assert_eq!(weak.score, 0);
+        assert_eq!(weak.crack_time, "less than a second");
+
+        let strong = password_report("Du8llZd$wZ9Lxc4v8Ez2")?;
+        assert_eq!(strong.score, 4);
+        assert_eq!(strong.crack_time, "centuries");
+        assert!(strong.guesses > weak.guesses);
+        Ok(())
+    }
+
+    #[test]
+    fn test_password_check_user_inputs() -> Result<()> {
+        let plain = password_check("acmecorp2024", &[])?;
+        let hinted = password_check("acmecorp2024", &["acmecorp"])?;
+        assert!(hinted.report.guesses < plain.report.guesses);
+
+        let weak = password_check("qwerty", &[])?;
+        assert!(weak.warning.is_some());
+        assert!(!weak.suggestions.is_empty());
+        Ok(())
+    }
+}
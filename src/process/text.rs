@@ -1,13 +1,19 @@
-use std::{collections::HashMap, io::Read};
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{self, Read},
+    path::Path,
+};
 
-use anyhow::{bail, Ok, Result};
+use anyhow::{anyhow, bail, Ok, Result};
 use chacha20poly1305::{
     aead::{generic_array::GenericArray, Aead, KeyInit, OsRng},
     ChaCha20Poly1305,
 };
-use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey, SIGNATURE_LENGTH};
+use sha2::{Digest, Sha512};
 
-use super::gen_pass::process_genpass;
+use super::gen_pass::{process_genpass, GenPassConfig};
 use crate::cli::TextSignFormat;
 
 pub trait TextSigner {
@@ -26,31 +32,46 @@ pub struct Blake3 {
 
 pub struct Ed25519Signer {
     key: SigningKey,
+    prehash: bool,
 }
 
 pub struct Ed25519Verifier {
     key: VerifyingKey,
 }
 
+/// Ed25519ph signatures are this tag followed by the 64 signature bytes, so the verifier
+/// can tell them from plain 64 byte Ed25519 signatures. The 65 byte layout is rcli's own,
+/// other tools expect just the 64 bytes.
+const ED25519PH_TAG: u8 = 0x01;
+/// Domain separation context for Ed25519ph (RFC 8032). Other RFC 8032 implementations
+/// can check the 64 signature bytes only when given this same context.
+const ED25519PH_CONTEXT: &[u8] = b"rcli-ed25519ph";
+
 impl TextSigner for Blake3 {
     fn sign(&self, reader: &mut dyn Read) -> Result<Vec<u8>> {
-        let mut buf = Vec::new();
-        reader.read_to_end(&mut buf)?;
-        Ok(blake3::keyed_hash(&self.key, &buf).as_bytes().to_vec())
+        Ok(self.hash_reader(reader)?.as_bytes().to_vec())
     }
 }
 
 impl TextVerifier for Blake3 {
     fn verify(&self, reader: &mut dyn Read, sig: &[u8]) -> Result<bool> {
-        let mut buf = Vec::new();
-        reader.read_to_end(&mut buf)?;
-        let ret = blake3::keyed_hash(&self.key, &buf);
-        Ok(ret.as_bytes() == sig)
+        let sig = blake3_signature(sig)?;
+        // `blake3::Hash` equality is constant-time
+        Ok(self.hash_reader(reader)? == sig)
     }
 }
 
 impl TextSigner for Ed25519Signer {
     fn sign(&self, reader: &mut dyn Read) -> Result<Vec<u8>> {
+        if self.prehash {
+            let signature = self
+                .key
+                .sign_prehashed(sha512_reader(reader)?, Some(ED25519PH_CONTEXT))?;
+            let mut sig = vec![ED25519PH_TAG];
+            sig.extend_from_slice(&signature.to_bytes());
+            return Ok(sig);
+        }
+        // plain Ed25519 hashes the message twice, so it has to be in memory
         let mut buf = Vec::new();
         reader.read_to_end(&mut buf)?;
         let signature = self.key.sign(&buf);
@@ -60,28 +81,97 @@ impl TextSigner for Ed25519Signer {
 
 impl TextVerifier for Ed25519Verifier {
     fn verify(&self, reader: &mut dyn Read, sig: &[u8]) -> Result<bool> {
-        let mut buf = Vec::new();
-        reader.read_to_end(&mut buf)?;
-        let sig = (sig[..]).try_into()?;
-        let signature = Signature::from_bytes(sig);
-        Ok(self.key.verify(&buf, &signature).is_ok())
+        match sig.len() {
+            SIGNATURE_LENGTH => {
+                let mut buf = Vec::new();
+                reader.read_to_end(&mut buf)?;
+                let signature = Signature::from_bytes(sig.try_into()?);
+                Ok(self.key.verify(&buf, &signature).is_ok())
+            }
+            65 if sig[0] == ED25519PH_TAG => {
+                let signature = Signature::from_bytes(sig[1..].try_into()?);
+                let prehashed = sha512_reader(reader)?;
+                Ok(self
+                    .key
+                    .verify_prehashed(prehashed, Some(ED25519PH_CONTEXT), &signature)
+                    .is_ok())
+            }
+            65 => bail!("Unknown Ed25519 signature mode {:#04x}", sig[0]),
+            n => bail!(
+                "Ed25519 signature must be {} bytes (65 for ed25519ph), got {}",
+                SIGNATURE_LENGTH,
+                n
+            ),
+        }
     }
 }
 
+/// 32 byte key from a key file, which must hold exactly the key and nothing else.
+fn key_bytes(name: &str, key: &[u8]) -> Result<[u8; 32]> {
+    key.try_into().map_err(|_| {
+        anyhow!(
+            "{} key must be exactly 32 bytes, got {}{}",
+            name,
+            key.len(),
+            if key.len() == 33 && key.ends_with(b"\n") {
+                " (the key file ends with a newline)"
+            } else {
+                ""
+            }
+        )
+    })
+}
+
+fn blake3_signature(sig: &[u8]) -> Result<blake3::Hash> {
+    let sig: [u8; blake3::OUT_LEN] = sig.try_into().map_err(|_| {
+        anyhow!(
+            "Blake3 signature must be {} bytes, got {}",
+            blake3::OUT_LEN,
+            sig.len()
+        )
+    })?;
+    Ok(blake3::Hash::from_bytes(sig))
+}
+
+fn sha512_reader(reader: &mut dyn Read) -> Result<Sha512> {
+    let mut hasher = Sha512::new();
+    io::copy(reader, &mut hasher)?;
+    Ok(hasher)
+}
+
 impl Blake3 {
     pub fn try_new(key: impl AsRef<[u8]>) -> Result<Self> {
-        let key = key.as_ref();
-        // convert &[u8] to [u8; 32]
-        let key: [u8; 32] = key[..32].try_into()?;
-        Ok(Self::new(key))
+        Ok(Self::new(key_bytes("Blake3", key.as_ref())?))
     }
 
     pub fn new(key: [u8; 32]) -> Self {
         Self { key }
     }
 
+    fn hash_reader(&self, reader: &mut dyn Read) -> Result<blake3::Hash> {
+        let mut hasher = blake3::Hasher::new_keyed(&self.key);
+        hasher.update_reader(reader)?;
+        Ok(hasher.finalize())
+    }
+
+    /// Memory map the file and hash it on all cores.
+    #[cfg(feature = "parallel")]
+    fn hash_file(&self, path: &Path) -> Result<blake3::Hash> {
+        let mut hasher = blake3::Hasher::new_keyed(&self.key);
+        hasher.update_mmap_rayon(path)?;
+        Ok(hasher.finalize())
+    }
+
+    #[cfg(not(feature = "parallel"))]
+    fn hash_file(&self, path: &Path) -> Result<blake3::Hash> {
+        self.hash_reader(&mut File::open(path)?)
+    }
+
     fn generate() -> Result<HashMap<&'static str, Vec<u8>>> {
-        let key = process_genpass(32, true, true, true, true)?;
+        let key = process_genpass(&GenPassConfig {
+            length: 32,
+            ..Default::default()
+        })?;
         let mut map = HashMap::new();
         map.insert("blake3.txt", key.as_bytes().to_vec());
         Ok(map)
@@ -90,14 +180,23 @@ impl Blake3 {
 
 impl Ed25519Signer {
     pub fn try_new(key: impl AsRef<[u8]>) -> Result<Self> {
-        let key = key.as_ref();
-        let key = (&key[..32]).try_into()?;
-        Ok(Self::new(key))
+        Ok(Self::new(&key_bytes("Ed25519 signing", key.as_ref())?))
     }
 
     pub fn new(key: &[u8; 32]) -> Self {
         let key = SigningKey::from_bytes(key);
-        Self { key }
+        Self {
+            key,
+            prehash: false,
+        }
+    }
+
+    /// Sign the SHA-512 of the input (Ed25519ph) instead, which streams the input. The
+    /// signature is rcli specific: `ED25519PH_TAG` plus 64 bytes, with the context
+    /// `rcli-ed25519ph`.
+    pub fn prehashed(mut self) -> Self {
+        self.prehash = true;
+        self
     }
 
     fn generate() -> Result<HashMap<&'static str, Vec<u8>>> {
@@ -114,9 +213,8 @@ impl Ed25519Signer {
 
 impl Ed25519Verifier {
     pub fn try_new(key: impl AsRef<[u8]>) -> Result<Self> {
-        let key = key.as_ref();
-        let key = (&key[..32]).try_into()?;
-        let key = VerifyingKey::from_bytes(key)?;
+        let key = key_bytes("Ed25519 verifying", key.as_ref())?;
+        let key = VerifyingKey::from_bytes(&key)?;
         Ok(Self { key })
     }
 }
@@ -129,10 +227,20 @@ pub fn process_text_sign(
     let signer: Box<dyn TextSigner> = match format {
         TextSignFormat::Blake3 => Box::new(Blake3::try_new(key)?),
         TextSignFormat::Ed25519 => Box::new(Ed25519Signer::try_new(key)?),
+        TextSignFormat::Ed25519ph => Box::new(Ed25519Signer::try_new(key)?.prehashed()),
     };
     signer.sign(reader)
 }
 
+/// Same as `process_text_sign` for a file, blake3 uses mmap and rayon when built
+/// with the `parallel` feature.
+pub fn process_text_sign_file(path: &Path, key: &[u8], format: TextSignFormat) -> Result<Vec<u8>> {
+    match format {
+        TextSignFormat::Blake3 => Ok(Blake3::try_new(key)?.hash_file(path)?.as_bytes().to_vec()),
+        _ => process_text_sign(&mut File::open(path)?, key, format),
+    }
+}
+
 pub fn process_text_verify(
     reader: &mut dyn Read,
     key: &[u8],
@@ -141,15 +249,33 @@ pub fn process_text_verify(
 ) -> Result<bool> {
     let verifier: Box<dyn TextVerifier> = match format {
         TextSignFormat::Blake3 => Box::new(Blake3::try_new(key)?),
-        TextSignFormat::Ed25519 => Box::new(Ed25519Verifier::try_new(key)?),
+        // the signature tells plain and prehashed apart
+        TextSignFormat::Ed25519 | TextSignFormat::Ed25519ph => {
+            Box::new(Ed25519Verifier::try_new(key)?)
+        }
     };
     verifier.verify(reader, sig)
 }
 
+pub fn process_text_verify_file(
+    path: &Path,
+    key: &[u8],
+    sig: &[u8],
+    format: TextSignFormat,
+) -> Result<bool> {
+    match format {
+        TextSignFormat::Blake3 => {
+            let sig = blake3_signature(sig)?;
+            Ok(Blake3::try_new(key)?.hash_file(path)? == sig)
+        }
+        _ => process_text_verify(&mut File::open(path)?, key, sig, format),
+    }
+}
+
 pub fn process_text_key_generate(format: TextSignFormat) -> Result<HashMap<&'static str, Vec<u8>>> {
     match format {
         TextSignFormat::Blake3 => Blake3::generate(),
-        TextSignFormat::Ed25519 => Ed25519Signer::generate(),
+        TextSignFormat::Ed25519 | TextSignFormat::Ed25519ph => Ed25519Signer::generate(),
     }
 }
 
@@ -244,16 +370,124 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_ed25519_plain_and_prehashed() -> Result<()> {
+        let sk = include_bytes!("../../fixtures/ed25519.sk");
+        let pk = include_bytes!("../../fixtures/ed25519.pk");
+        let content = vec![7u8; 1 << 20];
+
+        let sig = super::process_text_sign(&mut &content[..], sk, TextSignFormat::Ed25519)?;
+        assert_eq!(sig.len(), 64);
+        let ph = super::process_text_sign(&mut &content[..], sk, TextSignFormat::Ed25519ph)?;
+        assert_eq!(ph.len(), 65);
+
+        for sig in [&sig, &ph] {
+            // either format verifies either kind of signature
+            for format in [TextSignFormat::Ed25519, TextSignFormat::Ed25519ph] {
+                assert!(process_text_verify(&mut &content[..], pk, sig, format)?);
+                assert!(!process_text_verify(&mut &content[1..], pk, sig, format)?);
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_blake3_file() -> Result<()> {
+        let path =
+            std::env::temp_dir().join(format!("rcli_blake3_file_{}.txt", std::process::id()));
+        std::fs::write(&path, "hello")?;
+        let format = TextSignFormat::Blake3;
+        let sig = super::process_text_sign_file(&path, KEY, format)?;
+        assert_eq!(
+            sig,
+            super::process_text_sign(&mut "hello".as_bytes(), KEY, format)?
+        );
+        assert!(super::process_text_verify_file(&path, KEY, &sig, format)?);
+        std::fs::remove_file(path)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_malformed_keys() {
+        let mut newline = KEY.to_vec();
+        newline.push(b'\n');
+        let keys: [&[u8]; 4] = [b"", &KEY[..31], &newline, &[0u8; 64]];
+        for key in keys {
+            for format in [TextSignFormat::Blake3, TextSignFormat::Ed25519] {
+                let err = super::process_text_sign(&mut "hello".as_bytes(), key, format)
+                    .unwrap_err()
+                    .to_string();
+                assert!(err.contains("must be exactly 32 bytes"), "{}", err);
+                let err = process_text_verify(&mut "hello".as_bytes(), key, &[0; 64], format)
+                    .unwrap_err()
+                    .to_string();
+                assert!(err.contains("must be exactly 32 bytes"), "{}", err);
+            }
+        }
+        let err = super::Blake3::try_new(&newline)
+            .err()
+            .expect("a 33 byte key is rejected")
+            .to_string();
+        assert!(err.contains("ends with a newline"), "{}", err);
+    }
+
+    #[test]
+    fn test_malformed_signatures() -> Result<()> {
+        let pk = include_bytes!("../../fixtures/ed25519.pk");
+        let blake3 = TextSignFormat::Blake3;
+        for len in [0, 31, 33, 64] {
+            let err = process_text_verify(&mut "hello".as_bytes(), KEY, &vec![0; len], blake3)
+                .unwrap_err()
+                .to_string();
+            assert!(err.contains("Blake3 signature must be 32 bytes"), "{}", err);
+        }
+        // right length, wrong bytes
+        assert!(!process_text_verify(
+            &mut "hello".as_bytes(),
+            KEY,
+            &[0; 32],
+            blake3
+        )?);
+
+        let ed25519 = TextSignFormat::Ed25519;
+        for len in [0, 32, 63, 66] {
+            let err = process_text_verify(&mut "hello".as_bytes(), pk, &vec![0; len], ed25519)
+                .unwrap_err()
+                .to_string();
+            assert!(
+                err.contains("Ed25519 signature must be 64 bytes"),
+                "{}",
+                err
+            );
+        }
+        let err = process_text_verify(&mut "hello".as_bytes(), pk, &[0xff; 65], ed25519)
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("Unknown Ed25519 signature mode"), "{}", err);
+        assert!(!process_text_verify(
+            &mut "hello".as_bytes(),
+            pk,
+            &[0; 64],
+            ed25519
+        )?);
+        Ok(())
+    }
+
     #[test]
     #[allow(clippy::useless_asref)]
     fn test_encrypt_decrypt() -> Result<()> {
         let key = CHACHA20_POLY1305_DD;
         let content = "hello".as_bytes();
 
-        let ciphertext =
-            super::ChaCha20Poly1305DD::encrypt(&mut key.as_ref(), &mut content.as_ref())?;
-        let plaintext =
-            super::ChaCha20Poly1305DD::decrypt(&mut key.as_ref(), &mut ciphertext.as_slice())?;
+        // `as_ref::<[u8]>` since toml links winnow, whose `AsRef` impls make plain `as_ref()` ambiguous
+        let ciphertext = super::ChaCha20Poly1305DD::encrypt(
+            &mut AsRef::<[u8]>::as_ref(key),
+            &mut AsRef::<[u8]>::as_ref(content),
+        )?;
+        let plaintext = super::ChaCha20Poly1305DD::decrypt(
+            &mut AsRef::<[u8]>::as_ref(key),
+            &mut ciphertext.as_slice(),
+        )?;
         assert_eq!(content, plaintext.as_slice());
         Ok(())
     }
@@ -1,5 +1,10 @@
 use anyhow::Result;
-use std::{fs::File, io::Read};
+use serde::de::DeserializeOwned;
+use std::{
+    fs::{self, File},
+    io::{self, Read, Write},
+    path::Path,
+};
 
 pub fn get_reader(input: &str) -> Result<Box<dyn Read>> {
     let reader: Box<dyn Read> = if input == "-" {
@@ -10,9 +15,104 @@ pub fn get_reader(input: &str) -> Result<Box<dyn Read>> {
     Ok(reader)
 }
 
+pub fn get_writer(output: &str) -> Result<Box<dyn Write>> {
+    let writer: Box<dyn Write> = if output == "-" {
+        Box::new(std::io::stdout())
+    } else {
+        Box::new(File::create(output)?)
+    };
+    Ok(writer)
+}
+
+/// Like `get_writer`, but the file is only created (or truncated) on the first write or
+/// flush, so a command that fails while validating its options leaves the output alone.
+pub fn get_lazy_writer(output: &str) -> Result<Box<dyn Write>> {
+    let writer: Box<dyn Write> = if output == "-" {
+        Box::new(std::io::stdout())
+    } else {
+        Box::new(LazyFile {
+            path: output.to_string(),
+            file: None,
+        })
+    };
+    Ok(writer)
+}
+
+struct LazyFile {
+    path: String,
+    file: Option<File>,
+}
+
+impl LazyFile {
+    fn file(&mut self) -> io::Result<&mut File> {
+        if self.file.is_none() {
+            self.file = Some(File::create(&self.path)?);
+        }
+        Ok(self.file.as_mut().expect("the file was just created"))
+    }
+}
+
+impl Write for LazyFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.file()?.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file()?.flush()
+    }
+}
+
+/// Whether input and output name the same existing file, `-` never does.
+pub fn is_same_file(input: &str, output: &str) -> bool {
+    if input == "-" || output == "-" {
+        return false;
+    }
+    match (fs::canonicalize(input), fs::canonicalize(output)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
+}
+
+/// Load a config file: toml for a `.toml` extension, yaml (or json) otherwise.
+pub fn load_config<T: DeserializeOwned>(path: impl AsRef<Path>) -> Result<T> {
+    let path = path.as_ref();
+    let content = fs::read_to_string(path)?;
+    let config = match path.extension().and_then(|ext| ext.to_str()) {
+        Some("toml") => toml::from_str(&content)?,
+        // json is valid yaml
+        _ => serde_yaml::from_str(&content)?,
+    };
+    Ok(config)
+}
+
 pub fn get_content(input: &str) -> Result<Vec<u8>> {
     let mut reader = get_reader(input)?;
     let mut buf = Vec::new();
     reader.read_to_end(&mut buf)?;
     Ok(buf)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lazy_writer() -> Result<()> {
+        let path = std::env::temp_dir().join(format!("rcli_lazy_{}.txt", std::process::id()));
+        let path_str = path.to_str().unwrap();
+        fs::write(&path, "keep")?;
+
+        let writer = get_lazy_writer(path_str)?;
+        drop(writer);
+        assert_eq!(fs::read_to_string(&path)?, "keep");
+        assert!(is_same_file(path_str, path_str));
+        assert!(!is_same_file(path_str, "-"));
+
+        let mut writer = get_lazy_writer(path_str)?;
+        writer.write_all(b"new")?;
+        writer.flush()?;
+        assert_eq!(fs::read_to_string(&path)?, "new");
+        fs::remove_file(path)?;
+        Ok(())
+    }
+}